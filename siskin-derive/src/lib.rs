@@ -0,0 +1,510 @@
+//! `#[derive(Point)]` for user-defined siskin point types.
+//!
+//! Fields are mapped with `siskin` attributes:
+//!
+//! * `#[siskin(xyz)]` on the `Vector3<T>` position, required.
+//! * `#[siskin(normal)]` on a `Vector3<T>` normal, stored as `normal_x`, `normal_y`, `normal_z`.
+//! * `#[siskin(rgb)]` on a `Vector3<T>` color, stored as a packed `rgb` field.
+//! * `#[siskin(field)]` or `#[siskin(field = "name")]` on a scalar or array of scalars.
+//!
+//! Fields without attributes are not serialized and are set to their default value.
+//!
+//! The generated code refers to the crate as `::siskin`. Crates that re-export siskin
+//! under another name set the path with `#[siskin(crate = "path")]` on the struct.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Error, Expr, Fields, GenericArgument, Ident,
+    LitStr, PathArguments, Result, Type, WherePredicate,
+};
+
+enum Kind {
+    Xyz,
+    Normal,
+    Rgb,
+    Scalar(String),
+    Array(String, Box<Type>, Box<Expr>),
+    Other,
+}
+
+struct PointMember {
+    ident: Ident,
+    ty: Type,
+    kind: Kind,
+}
+
+/// Element type `T` of a `Vector3<T>` field.
+fn vector_element(ty: &Type) -> Result<Type> {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let Some(GenericArgument::Type(elem)) = args.args.first() {
+                    if segment.ident == "Vector3" {
+                        return Ok(elem.clone());
+                    }
+                }
+            }
+        }
+    }
+    Err(Error::new_spanned(ty, "expected a `Vector3<T>` field"))
+}
+
+fn parse_member(field: &syn::Field) -> Result<PointMember> {
+    let ident = field.ident.clone().expect("named field");
+    let mut kind = Kind::Other;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("siskin")) {
+        attr.parse_nested_meta(|meta| {
+            if !matches!(kind, Kind::Other) {
+                return Err(meta.error("a field takes a single siskin attribute"));
+            }
+            if meta.path.is_ident("xyz") {
+                kind = Kind::Xyz;
+            } else if meta.path.is_ident("normal") {
+                kind = Kind::Normal;
+            } else if meta.path.is_ident("rgb") {
+                kind = Kind::Rgb;
+            } else if meta.path.is_ident("field") {
+                let name = if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<LitStr>()?.value()
+                } else {
+                    ident.to_string()
+                };
+                kind = match &field.ty {
+                    Type::Array(array) => {
+                        Kind::Array(name, array.elem.clone(), Box::new(array.len.clone()))
+                    }
+                    _ => Kind::Scalar(name),
+                };
+            } else {
+                return Err(meta.error("expected `xyz`, `normal`, `rgb` or `field`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(PointMember {
+        ident,
+        ty: field.ty.clone(),
+        kind,
+    })
+}
+
+fn find<'a>(
+    members: &'a [PointMember],
+    f: impl Fn(&Kind) -> bool,
+    name: &str,
+    input: &DeriveInput,
+) -> Result<Option<&'a PointMember>> {
+    let mut found = members.iter().filter(|m| f(&m.kind));
+    let first = found.next();
+    if let Some(second) = found.next() {
+        return Err(Error::new_spanned(
+            &second.ident,
+            format!("only one field can be marked `{}`", name),
+        ));
+    }
+    if first.is_none() && name == "xyz" {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "a field must be marked `#[siskin(xyz)]`",
+        ));
+    }
+    Ok(first)
+}
+
+/// Path of the siskin crate, from the `#[siskin(crate = "...")]` struct attribute.
+fn crate_path(input: &DeriveInput) -> Result<TokenStream2> {
+    let mut path = quote!(::siskin);
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("siskin")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                let value = meta.value()?.parse::<LitStr>()?.parse::<syn::Path>()?;
+                path = quote!(#value);
+                Ok(())
+            } else {
+                Err(meta.error("expected `crate`"))
+            }
+        })?;
+    }
+    Ok(path)
+}
+
+#[proc_macro_derive(Point, attributes(siskin))]
+pub fn derive_point(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "Point can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "Point can only be derived for structs",
+            ))
+        }
+    };
+    let members = fields
+        .iter()
+        .map(parse_member)
+        .collect::<Result<Vec<_>>>()?;
+    let xyz = find(&members, |k| matches!(k, Kind::Xyz), "xyz", input)?.unwrap();
+    let normal = find(&members, |k| matches!(k, Kind::Normal), "normal", input)?;
+    let rgb = find(&members, |k| matches!(k, Kind::Rgb), "rgb", input)?;
+
+    let root = crate_path(input)?;
+    let p = quote!(#root::__private);
+    let name = &input.ident;
+    let item = vector_element(&xyz.ty)?;
+    let xyz_ident = &xyz.ident;
+
+    // Bounds shared by every generated impl.
+    let mut predicates: Vec<WherePredicate> = vec![parse_quote!(#item: #root::FloatData)];
+    for m in members.iter() {
+        let ty = &m.ty;
+        match &m.kind {
+            Kind::Xyz => {}
+            Kind::Normal => {
+                let elem = vector_element(ty)?;
+                predicates.push(parse_quote!(#elem: #root::FloatData));
+                predicates.push(parse_quote!(#ty: ::core::default::Default));
+            }
+            Kind::Rgb => {
+                let elem = vector_element(ty)?;
+                predicates.push(parse_quote!(#elem: #root::ColorData));
+                predicates.push(parse_quote!(#ty: ::core::default::Default));
+            }
+            Kind::Scalar(_) => predicates.push(parse_quote!(#ty: #root::FieldScalar)),
+            Kind::Array(_, elem, _) => predicates.push(parse_quote!(#elem: #root::FieldScalar)),
+            Kind::Other => predicates.push(parse_quote!(#ty: ::core::default::Default)),
+        }
+    }
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let where_clause = |extra: &[WherePredicate]| {
+        let mut generics = input.generics.clone();
+        let clause = generics.make_where_clause();
+        clause.predicates.extend(predicates.iter().cloned());
+        clause.predicates.extend(extra.iter().cloned());
+        quote!(#clause)
+    };
+    let base_where = where_clause(&[]);
+
+    // Struct literal with the given fields set and every other field zeroed.
+    let construct = |set: &[(&Ident, TokenStream2)]| {
+        let inits = members.iter().map(|m| {
+            let ident = &m.ident;
+            let ty = &m.ty;
+            if let Some((_, value)) = set.iter().find(|(i, _)| *i == ident) {
+                return quote!(#ident: #value);
+            }
+            match &m.kind {
+                Kind::Scalar(_) => quote!(#ident: <#ty as #p::num_traits::Zero>::zero()),
+                Kind::Array(_, elem, len) => {
+                    quote!(#ident: [<#elem as #p::num_traits::Zero>::zero(); #len])
+                }
+                _ => quote!(#ident: ::core::default::Default::default()),
+            }
+        });
+        quote!(#name { #(#inits,)* })
+    };
+
+    let mut field_list = Vec::new();
+    let mut get_arms = Vec::new();
+    let mut set_arms = Vec::new();
+    let mut get_array_arms = Vec::new();
+    let mut set_array_arms = Vec::new();
+    for m in members.iter() {
+        let ident = &m.ident;
+        let ty = &m.ty;
+        match &m.kind {
+            Kind::Xyz => {
+                for axis in ["x", "y", "z"].iter() {
+                    field_list.push(quote!(#root::PointField::new(
+                        #axis,
+                        #root::FieldType::float_of::<#item>()
+                    )));
+                }
+                get_arms.push(quote!(
+                    "x" | "y" | "z" => #p::to_f64(#root::Point::point_field(self, name)?)
+                ));
+                set_arms.push(quote!(
+                    "x" | "y" | "z" => {
+                        *#root::Point::point_field_mut(self, name)? = #p::from_f64(value)?
+                    }
+                ));
+            }
+            Kind::Normal => {
+                let elem = vector_element(ty)?;
+                for axis in ["normal_x", "normal_y", "normal_z"].iter() {
+                    field_list.push(quote!(#root::PointField::new(
+                        #axis,
+                        #root::FieldType::float_of::<#elem>()
+                    )));
+                }
+                get_arms.push(quote!(
+                    "normal_x" | "normal_y" | "normal_z" => {
+                        #p::to_f64(#root::Normal::normal_field(self, name)?)
+                    }
+                ));
+                set_arms.push(quote!(
+                    "normal_x" | "normal_y" | "normal_z" => {
+                        *#root::Normal::normal_field_mut(self, name)? = #p::from_f64(value)?
+                    }
+                ));
+            }
+            Kind::Rgb => {
+                field_list.push(quote!(#root::PointField::new("rgb", #root::FieldType::F32)));
+                get_arms.push(quote!("rgb" | "rgba" => Ok(#p::packed_color_value(self))));
+                set_arms.push(quote!("rgb" | "rgba" => #p::set_packed_color(self, value)));
+            }
+            Kind::Scalar(field_name) => {
+                field_list.push(quote!(#root::PointField::new(
+                    #field_name,
+                    <#ty as #root::FieldScalar>::FIELD_TYPE
+                )));
+                get_arms.push(quote!(#field_name => #p::to_f64(self.#ident)));
+                set_arms.push(quote!(#field_name => self.#ident = #p::from_f64(value)?));
+            }
+            Kind::Array(field_name, elem, len) => {
+                field_list.push(quote!(#root::PointField {
+                    name: #field_name.to_string(),
+                    datatype: <#elem as #root::FieldScalar>::FIELD_TYPE,
+                    count: #len,
+                }));
+                get_array_arms.push(quote!(
+                    #field_name => self.#ident.iter().map(|v| #p::to_f64(*v)).collect()
+                ));
+                set_array_arms.push(quote!(
+                    #field_name => {
+                        if values.len() != #len {
+                            return Err(#p::anyhow::anyhow!(format!(
+                                "Field {:?} holds {} values, got {}",
+                                name,
+                                #len,
+                                values.len()
+                            )));
+                        }
+                        for (d, v) in self.#ident.iter_mut().zip(values) {
+                            *d = #p::from_f64(*v)?;
+                        }
+                        Ok(())
+                    }
+                ));
+            }
+            Kind::Other => {}
+        }
+    }
+
+    let from_point = construct(&[(xyz_ident, quote!(point))]);
+    let transform_normal = normal.map(|normal| {
+        let ident = &normal.ident;
+        quote! {
+            fn transform_normal(&mut self, normal_matrix: &#p::nalgebra::Matrix3<#item>) {
+                #p::transform_normal_vector(normal_matrix, &mut self.#ident);
+            }
+        }
+    });
+    let mut tokens = quote! {
+        impl #impl_generics #root::Point for #name #ty_generics #base_where {
+            type Item = #item;
+            fn from_point(point: #p::nalgebra::Vector3<#item>) -> Self {
+                #from_point
+            }
+            fn xyz(&self) -> &#p::nalgebra::Vector3<#item> {
+                &self.#xyz_ident
+            }
+            fn xyz_mut(&mut self) -> &mut #p::nalgebra::Vector3<#item> {
+                &mut self.#xyz_ident
+            }
+            fn fields() -> ::std::vec::Vec<#root::PointField> {
+                vec![#(#field_list),*]
+            }
+            fn field_value(&self, name: &str) -> #p::anyhow::Result<f64> {
+                match name {
+                    #(#get_arms,)*
+                    _ => Err(#p::anyhow::anyhow!(format!("Invalid field name {:?}", name))),
+                }
+            }
+            fn set_field_value(&mut self, name: &str, value: f64) -> #p::anyhow::Result<()> {
+                match name {
+                    #(#set_arms,)*
+                    _ => return Err(#p::anyhow::anyhow!(format!("Invalid field name {:?}", name))),
+                }
+                Ok(())
+            }
+            fn field_values(&self, name: &str) -> #p::anyhow::Result<::std::vec::Vec<f64>> {
+                match name {
+                    #(#get_array_arms,)*
+                    _ => Ok(vec![#root::Point::field_value(self, name)?]),
+                }
+            }
+            fn set_field_values(&mut self, name: &str, values: &[f64]) -> #p::anyhow::Result<()> {
+                match name {
+                    #(#set_array_arms,)*
+                    _ => match values {
+                        [value] => #root::Point::set_field_value(self, name, *value),
+                        _ => Err(#p::anyhow::anyhow!(format!(
+                            "Field {:?} does not hold {} values",
+                            name,
+                            values.len()
+                        ))),
+                    },
+                }
+            }
+            #transform_normal
+        }
+    };
+
+    if let Some(rgb) = rgb {
+        let ident = &rgb.ident;
+        let elem = vector_element(&rgb.ty)?;
+        let from_point_color = construct(&[(xyz_ident, quote!(point)), (ident, quote!(color))]);
+        tokens.extend(quote! {
+            impl #impl_generics #root::Color for #name #ty_generics #base_where {
+                type Item = #elem;
+                fn rgb(&self) -> &#p::nalgebra::Vector3<#elem> {
+                    &self.#ident
+                }
+                fn rgb_mut(&mut self) -> &mut #p::nalgebra::Vector3<#elem> {
+                    &mut self.#ident
+                }
+            }
+            impl #impl_generics #root::PointColor for #name #ty_generics #base_where {
+                fn from_point_color(
+                    point: #p::nalgebra::Vector3<#item>,
+                    color: #p::nalgebra::Vector3<#elem>,
+                ) -> Self {
+                    #from_point_color
+                }
+            }
+        });
+    }
+    if let Some(normal) = normal {
+        let ident = &normal.ident;
+        let elem = vector_element(&normal.ty)?;
+        let from_point_normal = construct(&[(xyz_ident, quote!(point)), (ident, quote!(normal))]);
+        tokens.extend(quote! {
+            impl #impl_generics #root::Normal for #name #ty_generics #base_where {
+                type Item = #elem;
+                fn normal(&self) -> &#p::nalgebra::Vector3<#elem> {
+                    &self.#ident
+                }
+                fn normal_mut(&mut self) -> &mut #p::nalgebra::Vector3<#elem> {
+                    &mut self.#ident
+                }
+            }
+            impl #impl_generics #root::PointNormal for #name #ty_generics #base_where {
+                fn from_point_normal(
+                    point: #p::nalgebra::Vector3<#item>,
+                    normal: #p::nalgebra::Vector3<#elem>,
+                ) -> Self {
+                    #from_point_normal
+                }
+            }
+        });
+    }
+    if let (Some(rgb), Some(normal)) = (rgb, normal) {
+        let color_elem = vector_element(&rgb.ty)?;
+        let normal_elem = vector_element(&normal.ty)?;
+        let from_point_color_normal = construct(&[
+            (xyz_ident, quote!(point)),
+            (&rgb.ident, quote!(color)),
+            (&normal.ident, quote!(normal)),
+        ]);
+        tokens.extend(quote! {
+            impl #impl_generics #root::PointColorNormal for #name #ty_generics #base_where {
+                fn from_point_color_normal(
+                    point: #p::nalgebra::Vector3<#item>,
+                    color: #p::nalgebra::Vector3<#color_elem>,
+                    normal: #p::nalgebra::Vector3<#normal_elem>,
+                ) -> Self {
+                    #from_point_color_normal
+                }
+            }
+        });
+    }
+
+    // Serialized floating point fields are summed and divided element-wise, so that
+    // filters can average points. Integer fields, such as labels or rings, and fields
+    // without attributes keep the value of the left operand.
+    let mut arith_predicates: Vec<WherePredicate> = Vec::new();
+    let mut add_fields = Vec::new();
+    let mut div_fields = Vec::new();
+    for m in members.iter() {
+        let ident = &m.ident;
+        let ty = &m.ty;
+        match &m.kind {
+            Kind::Xyz | Kind::Normal | Kind::Rgb => {
+                let elem = vector_element(ty)?;
+                arith_predicates.push(parse_quote!(
+                    #elem: #p::nalgebra::Scalar + #root::FieldScalar
+                ));
+                add_fields.push(quote!(
+                    #ident: self.#ident.zip_map(&other.#ident, #p::add_value)
+                ));
+                div_fields.push(quote!(#ident: self.#ident.map(|v| #p::div_value(v, divisor))));
+            }
+            Kind::Scalar(_) => {
+                add_fields.push(quote!(#ident: #p::add_value(self.#ident, other.#ident)));
+                div_fields.push(quote!(#ident: #p::div_value(self.#ident, divisor)));
+            }
+            Kind::Array(..) => {
+                add_fields.push(quote!(#ident: {
+                    let mut sum = self.#ident;
+                    for (s, o) in sum.iter_mut().zip(other.#ident.iter()) {
+                        *s = #p::add_value(*s, *o);
+                    }
+                    sum
+                }));
+                div_fields.push(quote!(#ident: {
+                    let mut quotient = self.#ident;
+                    for q in quotient.iter_mut() {
+                        *q = #p::div_value(*q, divisor);
+                    }
+                    quotient
+                }));
+            }
+            Kind::Other => {
+                add_fields.push(quote!(#ident: self.#ident));
+                div_fields.push(quote!(#ident: self.#ident));
+            }
+        }
+    }
+    let arith_where = where_clause(&arith_predicates);
+    let kd_where = where_clause(&[parse_quote!(#item: #p::num_traits::NumAssign)]);
+    tokens.extend(quote! {
+        impl #impl_generics ::core::ops::Add for #name #ty_generics #arith_where {
+            type Output = Self;
+            fn add(self, other: Self) -> Self {
+                #name { #(#add_fields,)* }
+            }
+        }
+        impl #impl_generics ::core::ops::Div<#item> for #name #ty_generics #arith_where {
+            type Output = Self;
+            fn div(self, divisor: #item) -> Self {
+                #name { #(#div_fields,)* }
+            }
+        }
+        impl #impl_generics #p::kd_tree::KdPoint for #name #ty_generics #kd_where {
+            type Scalar = #item;
+            type Dim = #p::typenum::U3;
+            fn at(&self, k: usize) -> #item {
+                self.#xyz_ident[k]
+            }
+        }
+    });
+    Ok(tokens)
+}
@@ -0,0 +1,411 @@
+use crate::pointcloud::*;
+use anyhow::{anyhow, Result};
+use nalgebra::{Matrix3, Scalar, Vector2, Vector3};
+use num_traits::ToPrimitive;
+
+/// Lens distortion of a pinhole camera, applied to normalized image coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Distortion {
+    #[default]
+    None,
+    /// OpenCV `plumb_bob` / `rational_polynomial` model: radial `k1` to `k6`, where
+    /// `k4`, `k5` and `k6` form the denominator, and tangential `p1`, `p2`.
+    BrownConrady {
+        radial: [f64; 6],
+        tangential: [f64; 2],
+    },
+    /// OpenCV fisheye / ROS `equidistant` model with coefficients `k1` to `k4`.
+    KannalaBrandt { k: [f64; 4] },
+}
+
+const UNDISTORT_ITERATIONS: usize = 20;
+
+impl Distortion {
+    /// Distorts normalized coordinates `(X / Z, Y / Z)`.
+    pub fn distort(&self, p: &Vector2<f64>) -> Vector2<f64> {
+        match self {
+            Distortion::None => *p,
+            Distortion::BrownConrady { radial, tangential } => {
+                let (x, y) = (p[0], p[1]);
+                let r2 = x * x + y * y;
+                let [k1, k2, k3, k4, k5, k6] = *radial;
+                let [p1, p2] = *tangential;
+                let scale = (1.0 + r2 * (k1 + r2 * (k2 + r2 * k3)))
+                    / (1.0 + r2 * (k4 + r2 * (k5 + r2 * k6)));
+                Vector2::new(
+                    x * scale + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+                    y * scale + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y,
+                )
+            }
+            Distortion::KannalaBrandt { k } => {
+                let r = p.norm();
+                if r < f64::EPSILON {
+                    return *p;
+                }
+                let theta = r.atan();
+                let theta2 = theta * theta;
+                let theta_d = theta
+                    * (1.0 + theta2 * (k[0] + theta2 * (k[1] + theta2 * (k[2] + theta2 * k[3]))));
+                p * (theta_d / r)
+            }
+        }
+    }
+
+    /// Inverts `distort` iteratively.
+    pub fn undistort(&self, p: &Vector2<f64>) -> Vector2<f64> {
+        match self {
+            Distortion::None => *p,
+            Distortion::BrownConrady { radial, tangential } => {
+                let [k1, k2, k3, k4, k5, k6] = *radial;
+                let [p1, p2] = *tangential;
+                let (mut x, mut y) = (p[0], p[1]);
+                for _ in 0..UNDISTORT_ITERATIONS {
+                    let r2 = x * x + y * y;
+                    let inv_scale = (1.0 + r2 * (k4 + r2 * (k5 + r2 * k6)))
+                        / (1.0 + r2 * (k1 + r2 * (k2 + r2 * k3)));
+                    let dx = 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+                    let dy = p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+                    x = (p[0] - dx) * inv_scale;
+                    y = (p[1] - dy) * inv_scale;
+                }
+                Vector2::new(x, y)
+            }
+            Distortion::KannalaBrandt { k } => {
+                let theta_d = p.norm();
+                if theta_d < f64::EPSILON {
+                    return *p;
+                }
+                // Newton iterations on theta * (1 + k1 theta^2 + ...) = theta_d.
+                let mut theta = theta_d;
+                for _ in 0..UNDISTORT_ITERATIONS {
+                    let t2 = theta * theta;
+                    let f = theta * (1.0 + t2 * (k[0] + t2 * (k[1] + t2 * (k[2] + t2 * k[3]))))
+                        - theta_d;
+                    let df = 1.0
+                        + t2 * (3.0 * k[0]
+                            + t2 * (5.0 * k[1] + t2 * (7.0 * k[2] + t2 * 9.0 * k[3])));
+                    if df.abs() < f64::EPSILON {
+                        break;
+                    }
+                    theta -= f / df;
+                }
+                p * (theta.tan() / theta_d)
+            }
+        }
+    }
+}
+
+/// Mapping between pixels and points in the camera frame.
+pub trait CameraModel {
+    /// Point at `depth` along the optical axis seen at `pixel`.
+    fn back_project(&self, pixel: &Vector2<f64>, depth: f64) -> Vector3<f64>;
+    /// Pixel where `point` is seen, or `None` if it is behind the camera.
+    fn project(&self, point: &Vector3<f64>) -> Option<Vector2<f64>>;
+    /// Projects every point of a cloud.
+    fn project_pointcloud<T: Point>(&self, pointcloud: &PointCloud<T>) -> Vec<Option<Vector2<f64>>>
+    where
+        Self: Sized,
+    {
+        pointcloud
+            .data
+            .iter()
+            .map(|p| self.project(&p.xyz().map(|v| v.to_f64().unwrap_or(f64::NAN))))
+            .collect()
+    }
+}
+
+/// A 3x3 intrinsic matrix is an undistorted pinhole camera.
+impl<N> CameraModel for Matrix3<N>
+where
+    N: Scalar + ToPrimitive,
+{
+    fn back_project(&self, pixel: &Vector2<f64>, depth: f64) -> Vector3<f64> {
+        PinholeCameraIntrinsic::from_matrix(0, 0, self).back_project(pixel, depth)
+    }
+    fn project(&self, point: &Vector3<f64>) -> Option<Vector2<f64>> {
+        PinholeCameraIntrinsic::from_matrix(0, 0, self).project(point)
+    }
+}
+
+impl<C: CameraModel> CameraModel for &C {
+    fn back_project(&self, pixel: &Vector2<f64>, depth: f64) -> Vector3<f64> {
+        (*self).back_project(pixel, depth)
+    }
+    fn project(&self, point: &Vector3<f64>) -> Option<Vector2<f64>> {
+        (*self).project(point)
+    }
+}
+
+/// Pinhole camera with image size and lens distortion.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PinholeCameraIntrinsic {
+    pub width: u32,
+    pub height: u32,
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    pub distortion: Distortion,
+}
+
+impl CameraModel for PinholeCameraIntrinsic {
+    fn back_project(&self, pixel: &Vector2<f64>, depth: f64) -> Vector3<f64> {
+        let distorted = Vector2::new(
+            (pixel[0] - self.cx) / self.fx,
+            (pixel[1] - self.cy) / self.fy,
+        );
+        let p = self.distortion.undistort(&distorted);
+        Vector3::new(p[0] * depth, p[1] * depth, depth)
+    }
+    fn project(&self, point: &Vector3<f64>) -> Option<Vector2<f64>> {
+        if point[2] <= 0.0 {
+            return None;
+        }
+        let p = self
+            .distortion
+            .distort(&Vector2::new(point[0] / point[2], point[1] / point[2]));
+        Some(Vector2::new(
+            self.fx * p[0] + self.cx,
+            self.fy * p[1] + self.cy,
+        ))
+    }
+}
+
+fn yaml_f64s(value: &serde_yaml::Value) -> Option<Vec<f64>> {
+    value.as_sequence()?.iter().map(|v| v.as_f64()).collect()
+}
+
+impl PinholeCameraIntrinsic {
+    pub fn new(
+        width: u32,
+        height: u32,
+        fx: f64,
+        fy: f64,
+        cx: f64,
+        cy: f64,
+    ) -> PinholeCameraIntrinsic {
+        PinholeCameraIntrinsic {
+            width,
+            height,
+            fx,
+            fy,
+            cx,
+            cy,
+            distortion: Distortion::None,
+        }
+    }
+
+    pub fn with_distortion(self, distortion: Distortion) -> PinholeCameraIntrinsic {
+        PinholeCameraIntrinsic { distortion, ..self }
+    }
+
+    /// Builds an undistorted camera from a 3x3 intrinsic matrix.
+    pub fn from_matrix<N>(width: u32, height: u32, k: &Matrix3<N>) -> PinholeCameraIntrinsic
+    where
+        N: Scalar + ToPrimitive,
+    {
+        let k = k.map(|v| v.to_f64().unwrap_or(0.0));
+        PinholeCameraIntrinsic::new(width, height, k[(0, 0)], k[(1, 1)], k[(0, 2)], k[(1, 2)])
+    }
+
+    pub fn matrix(&self) -> Matrix3<f64> {
+        Matrix3::new(self.fx, 0.0, self.cx, 0.0, self.fy, self.cy, 0.0, 0.0, 1.0)
+    }
+
+    /// Reads an Open3D `PinholeCameraIntrinsic` JSON file.
+    pub fn from_open3d_json(filename: &str) -> Result<PinholeCameraIntrinsic> {
+        PinholeCameraIntrinsic::from_open3d_json_str(&std::fs::read_to_string(filename)?)
+    }
+
+    /// Parses Open3D JSON, whose `intrinsic_matrix` is stored column-major.
+    pub fn from_open3d_json_str(json: &str) -> Result<PinholeCameraIntrinsic> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let dimension = |key: &str| {
+            value[key]
+                .as_u64()
+                .map(|v| v as u32)
+                .ok_or_else(|| anyhow!(format!("Missing {:?} in camera JSON", key)))
+        };
+        let k = value["intrinsic_matrix"]
+            .as_array()
+            .and_then(|a| a.iter().map(|v| v.as_f64()).collect::<Option<Vec<_>>>())
+            .filter(|k| k.len() == 9)
+            .ok_or_else(|| anyhow!("Expected 9 values in intrinsic_matrix"))?;
+        Ok(PinholeCameraIntrinsic::new(
+            dimension("width")?,
+            dimension("height")?,
+            k[0],
+            k[4],
+            k[6],
+            k[7],
+        ))
+    }
+
+    /// Reads a ROS camera calibration YAML file.
+    pub fn from_camera_info_yaml(filename: &str) -> Result<PinholeCameraIntrinsic> {
+        PinholeCameraIntrinsic::from_camera_info_yaml_str(&std::fs::read_to_string(filename)?)
+    }
+
+    /// Parses ROS camera calibration YAML, either as written by `camera_calibration`
+    /// (`image_width`, `camera_matrix`, `distortion_coefficients`) or as a
+    /// `sensor_msgs/CameraInfo` message (`width`, `K`, `D`).
+    ///
+    /// The `plumb_bob`, `rational_polynomial` and `equidistant` models are supported.
+    pub fn from_camera_info_yaml_str(yaml: &str) -> Result<PinholeCameraIntrinsic> {
+        let value: serde_yaml::Value = serde_yaml::from_str(yaml)?;
+        let entry = |keys: &[&str]| keys.iter().map(|k| &value[*k]).find(|v| !v.is_null());
+        let matrix = |keys: &[&str]| {
+            entry(keys).and_then(|v| match v.get("data") {
+                Some(data) => yaml_f64s(data),
+                None => yaml_f64s(v),
+            })
+        };
+        let dimension = |keys: &[&str]| {
+            entry(keys)
+                .and_then(|v| v.as_u64())
+                .map(|v| v as u32)
+                .ok_or_else(|| anyhow!(format!("Missing {:?} in camera YAML", keys[0])))
+        };
+        let k = matrix(&["camera_matrix", "K", "k"])
+            .filter(|k| k.len() == 9)
+            .ok_or_else(|| anyhow!("Expected 9 values in camera_matrix"))?;
+        let d = matrix(&["distortion_coefficients", "D", "d"]).unwrap_or_default();
+        let model = entry(&["distortion_model"])
+            .and_then(|v| v.as_str())
+            .unwrap_or("plumb_bob");
+        let coefficient = |i: usize| d.get(i).copied().unwrap_or(0.0);
+        let distortion = if d.iter().all(|v| *v == 0.0) {
+            Distortion::None
+        } else {
+            match model {
+                "plumb_bob" | "rational_polynomial" => Distortion::BrownConrady {
+                    radial: [
+                        coefficient(0),
+                        coefficient(1),
+                        coefficient(4),
+                        coefficient(5),
+                        coefficient(6),
+                        coefficient(7),
+                    ],
+                    tangential: [coefficient(2), coefficient(3)],
+                },
+                "equidistant" | "fisheye" => Distortion::KannalaBrandt {
+                    k: [
+                        coefficient(0),
+                        coefficient(1),
+                        coefficient(2),
+                        coefficient(3),
+                    ],
+                },
+                _ => return Err(anyhow!(format!("Unsupported distortion model {:?}", model))),
+            }
+        };
+        Ok(PinholeCameraIntrinsic::new(
+            dimension(&["image_width", "width"])?,
+            dimension(&["image_height", "height"])?,
+            k[0],
+            k[4],
+            k[2],
+            k[5],
+        )
+        .with_distortion(distortion))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_round_trip(distortion: &Distortion, max_norm: f64) {
+        for i in -4..=4 {
+            for j in -4..=4 {
+                let p = Vector2::new(i as f64, j as f64) * (max_norm / 4.0);
+                let distorted = distortion.distort(&p);
+                let undistorted = distortion.undistort(&distorted);
+                assert!(
+                    (undistorted - p).norm() < 1e-6,
+                    "{:?}: {} -> {} -> {}",
+                    distortion,
+                    p,
+                    distorted,
+                    undistorted
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn brown_conrady_round_trip() {
+        let plumb_bob = Distortion::BrownConrady {
+            radial: [-0.28, 0.07, 0.0, 0.0, 0.0, 0.0],
+            tangential: [1e-3, -2e-3],
+        };
+        check_round_trip(&plumb_bob, 0.5);
+        let rational = Distortion::BrownConrady {
+            radial: [0.5, -0.1, 0.01, 0.6, -0.05, 0.02],
+            tangential: [-5e-4, 3e-4],
+        };
+        check_round_trip(&rational, 0.5);
+        assert!(
+            (plumb_bob.distort(&Vector2::new(0.3, 0.0)) - Vector2::new(0.3, 0.0)).norm() > 0.005
+        );
+    }
+
+    #[test]
+    fn kannala_brandt_round_trip() {
+        let fisheye = Distortion::KannalaBrandt {
+            k: [-0.01, 0.02, -0.005, 0.001],
+        };
+        // Normalized coordinates of 2 reach 63 degrees off the optical axis.
+        check_round_trip(&fisheye, 2.0);
+        let p = Vector2::new(1.0, 0.0);
+        let theta = std::f64::consts::FRAC_PI_4;
+        let theta2 = theta * theta;
+        let expected =
+            theta * (1.0 + theta2 * (-0.01 + theta2 * (0.02 + theta2 * (-0.005 + theta2 * 0.001))));
+        assert!((fisheye.distort(&p)[0] - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn project_back_project() {
+        let camera = PinholeCameraIntrinsic::new(640, 480, 500.0, 510.0, 320.0, 240.0)
+            .with_distortion(Distortion::KannalaBrandt {
+                k: [0.05, -0.01, 0.0, 0.0],
+            });
+        let point = Vector3::new(0.4, -0.3, 2.0);
+        let pixel = camera.project(&point).unwrap();
+        let back = camera.back_project(&pixel, 2.0);
+        assert!((back - point).norm() < 1e-9);
+        assert!(camera.project(&Vector3::new(0.0, 0.0, -1.0)).is_none());
+    }
+
+    #[test]
+    fn camera_info_yaml() {
+        let yaml = "
+image_width: 640
+image_height: 480
+camera_matrix:
+  rows: 3
+  cols: 3
+  data: [500.0, 0.0, 320.0, 0.0, 510.0, 240.0, 0.0, 0.0, 1.0]
+distortion_model: plumb_bob
+distortion_coefficients:
+  rows: 1
+  cols: 5
+  data: [-0.28, 0.07, 0.001, -0.002, 0.01]
+";
+        let camera = PinholeCameraIntrinsic::from_camera_info_yaml_str(yaml).unwrap();
+        assert_eq!((camera.width, camera.height), (640, 480));
+        assert_eq!(
+            (camera.fx, camera.fy, camera.cx, camera.cy),
+            (500.0, 510.0, 320.0, 240.0)
+        );
+        assert_eq!(
+            camera.distortion,
+            Distortion::BrownConrady {
+                radial: [-0.28, 0.07, 0.01, 0.0, 0.0, 0.0],
+                tangential: [0.001, -0.002],
+            }
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use super::pointcloud::{is_packed_color, FieldType, FloatData, Point, PointCloud, PointField};
+use anyhow::{anyhow, Result};
+use core::convert::TryInto;
+use num_traits::NumAssign;
+
+/// Values of one field for every point of a `DynamicPointCloud`.
+///
+/// Fields with `count > 1` store the elements of each point contiguously.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldData {
+    I8(Vec<i8>),
+    U8(Vec<u8>),
+    I16(Vec<i16>),
+    U16(Vec<u16>),
+    I32(Vec<i32>),
+    U32(Vec<u32>),
+    I64(Vec<i64>),
+    U64(Vec<u64>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+impl FieldData {
+    pub fn new(datatype: FieldType) -> FieldData {
+        match datatype {
+            FieldType::I8 => FieldData::I8(Vec::new()),
+            FieldType::U8 => FieldData::U8(Vec::new()),
+            FieldType::I16 => FieldData::I16(Vec::new()),
+            FieldType::U16 => FieldData::U16(Vec::new()),
+            FieldType::I32 => FieldData::I32(Vec::new()),
+            FieldType::U32 => FieldData::U32(Vec::new()),
+            FieldType::I64 => FieldData::I64(Vec::new()),
+            FieldType::U64 => FieldData::U64(Vec::new()),
+            FieldType::F32 => FieldData::F32(Vec::new()),
+            FieldType::F64 => FieldData::F64(Vec::new()),
+        }
+    }
+    pub fn datatype(&self) -> FieldType {
+        match self {
+            FieldData::I8(_) => FieldType::I8,
+            FieldData::U8(_) => FieldType::U8,
+            FieldData::I16(_) => FieldType::I16,
+            FieldData::U16(_) => FieldType::U16,
+            FieldData::I32(_) => FieldType::I32,
+            FieldData::U32(_) => FieldType::U32,
+            FieldData::I64(_) => FieldType::I64,
+            FieldData::U64(_) => FieldType::U64,
+            FieldData::F32(_) => FieldType::F32,
+            FieldData::F64(_) => FieldType::F64,
+        }
+    }
+    /// Number of stored elements.
+    pub fn len(&self) -> usize {
+        match self {
+            FieldData::I8(v) => v.len(),
+            FieldData::U8(v) => v.len(),
+            FieldData::I16(v) => v.len(),
+            FieldData::U16(v) => v.len(),
+            FieldData::I32(v) => v.len(),
+            FieldData::U32(v) => v.len(),
+            FieldData::I64(v) => v.len(),
+            FieldData::U64(v) => v.len(),
+            FieldData::F32(v) => v.len(),
+            FieldData::F64(v) => v.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Element at `index` converted to `f64`.
+    pub fn get(&self, index: usize) -> f64 {
+        match self {
+            FieldData::I8(v) => v[index] as f64,
+            FieldData::U8(v) => v[index] as f64,
+            FieldData::I16(v) => v[index] as f64,
+            FieldData::U16(v) => v[index] as f64,
+            FieldData::I32(v) => v[index] as f64,
+            FieldData::U32(v) => v[index] as f64,
+            FieldData::I64(v) => v[index] as f64,
+            FieldData::U64(v) => v[index] as f64,
+            FieldData::F32(v) => v[index] as f64,
+            FieldData::F64(v) => v[index],
+        }
+    }
+    /// Appends `value` converted to the stored type.
+    pub fn push(&mut self, value: f64) {
+        match self {
+            FieldData::I8(v) => v.push(value as i8),
+            FieldData::U8(v) => v.push(value as u8),
+            FieldData::I16(v) => v.push(value as i16),
+            FieldData::U16(v) => v.push(value as u16),
+            FieldData::I32(v) => v.push(value as i32),
+            FieldData::U32(v) => v.push(value as u32),
+            FieldData::I64(v) => v.push(value as i64),
+            FieldData::U64(v) => v.push(value as u64),
+            FieldData::F32(v) => v.push(value as f32),
+            FieldData::F64(v) => v.push(value),
+        }
+    }
+    /// Appends an element decoded from its little-endian bytes, without conversion.
+    pub(crate) fn push_le_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        match self {
+            FieldData::I8(v) => v.push(i8::from_le_bytes(bytes.try_into()?)),
+            FieldData::U8(v) => v.push(u8::from_le_bytes(bytes.try_into()?)),
+            FieldData::I16(v) => v.push(i16::from_le_bytes(bytes.try_into()?)),
+            FieldData::U16(v) => v.push(u16::from_le_bytes(bytes.try_into()?)),
+            FieldData::I32(v) => v.push(i32::from_le_bytes(bytes.try_into()?)),
+            FieldData::U32(v) => v.push(u32::from_le_bytes(bytes.try_into()?)),
+            FieldData::I64(v) => v.push(i64::from_le_bytes(bytes.try_into()?)),
+            FieldData::U64(v) => v.push(u64::from_le_bytes(bytes.try_into()?)),
+            FieldData::F32(v) => v.push(f32::from_le_bytes(bytes.try_into()?)),
+            FieldData::F64(v) => v.push(f64::from_le_bytes(bytes.try_into()?)),
+        }
+        Ok(())
+    }
+    /// Appends the little-endian bytes of the element at `index` to `out`.
+    pub(crate) fn extend_le_bytes(&self, index: usize, out: &mut Vec<u8>) {
+        match self {
+            FieldData::I8(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+            FieldData::U8(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+            FieldData::I16(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+            FieldData::U16(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+            FieldData::I32(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+            FieldData::U32(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+            FieldData::I64(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+            FieldData::U64(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+            FieldData::F32(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+            FieldData::F64(v) => out.extend_from_slice(&v[index].to_le_bytes()),
+        }
+    }
+    /// Appends an element parsed from text in the stored type.
+    pub(crate) fn push_str(&mut self, text: &str) -> Result<()> {
+        let invalid = || anyhow!(format!("Invalid value {:?}", text));
+        match self {
+            FieldData::I8(v) => v.push(text.parse().map_err(|_| invalid())?),
+            FieldData::U8(v) => v.push(text.parse().map_err(|_| invalid())?),
+            FieldData::I16(v) => v.push(text.parse().map_err(|_| invalid())?),
+            FieldData::U16(v) => v.push(text.parse().map_err(|_| invalid())?),
+            FieldData::I32(v) => v.push(text.parse().map_err(|_| invalid())?),
+            FieldData::U32(v) => v.push(text.parse().map_err(|_| invalid())?),
+            FieldData::I64(v) => v.push(text.parse().map_err(|_| invalid())?),
+            FieldData::U64(v) => v.push(text.parse().map_err(|_| invalid())?),
+            FieldData::F32(v) => v.push(text.parse().map_err(|_| invalid())?),
+            FieldData::F64(v) => v.push(text.parse().map_err(|_| invalid())?),
+        }
+        Ok(())
+    }
+}
+
+/// A point cloud whose fields are described at runtime.
+///
+/// Each field of `fields` is stored in the column at the same index, so that fields
+/// such as intensity, ring or timestamp are kept even when no point type carries them.
+/// Packed colors (`rgb`, `rgba`) keep the bit pattern of the packed word.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DynamicPointCloud {
+    fields: Vec<PointField>,
+    columns: Vec<FieldData>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DynamicPointCloud {
+    /// Creates an empty cloud with the given schema.
+    pub fn new(fields: Vec<PointField>) -> DynamicPointCloud {
+        let columns = fields.iter().map(|f| FieldData::new(f.datatype)).collect();
+        DynamicPointCloud {
+            fields,
+            columns,
+            width: 1,
+            height: 1,
+        }
+    }
+    pub fn len(&self) -> usize {
+        match (self.fields.first(), self.columns.first()) {
+            (Some(f), Some(c)) if f.count > 0 => c.len() / f.count,
+            _ => 0,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Schema of the cloud, one field per column.
+    pub fn fields(&self) -> &[PointField] {
+        &self.fields
+    }
+    /// Columns of the cloud, in the order of `fields`.
+    pub fn columns(&self) -> &[FieldData] {
+        &self.columns
+    }
+    pub(crate) fn columns_mut(&mut self) -> &mut [FieldData] {
+        &mut self.columns
+    }
+    /// Returns true if the points are laid out as a `width` x `height` grid.
+    pub fn is_organized(&self) -> bool {
+        self.height > 1
+    }
+    /// Index of the named field in `fields` and `columns`.
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f.name == name)
+    }
+    /// Column holding the named field.
+    pub fn column(&self, name: &str) -> Option<&FieldData> {
+        self.field_index(name).map(|i| &self.columns[i])
+    }
+    pub fn column_mut(&mut self, name: &str) -> Option<&mut FieldData> {
+        self.field_index(name).map(move |i| &mut self.columns[i])
+    }
+    /// Values of the named field of point `index`.
+    ///
+    /// Packed colors are returned as the integer value of the packed word. Returns an
+    /// error if the field does not exist or `index` is out of range.
+    pub fn field_values(&self, index: usize, name: &str) -> Result<Vec<f64>> {
+        let i = self
+            .field_index(name)
+            .ok_or_else(|| anyhow!(format!("Invalid field name {:?}", name)))?;
+        if index >= self.len() {
+            return Err(anyhow!(format!(
+                "Point index {} is out of range for {} points",
+                index,
+                self.len()
+            )));
+        }
+        let count = self.fields[i].count;
+        let column = &self.columns[i];
+        Ok((index * count..(index + 1) * count)
+            .map(|j| match column {
+                FieldData::F32(v) if is_packed_color(name) => v[j].to_bits() as f64,
+                _ => column.get(j),
+            })
+            .collect())
+    }
+    /// Appends one point given the values of every field, in schema order.
+    ///
+    /// Packed colors are given as the integer value of the packed word.
+    pub fn push_point(&mut self, values: &[Vec<f64>]) -> Result<()> {
+        if values.len() != self.fields.len() {
+            return Err(anyhow!(format!(
+                "Expected values for {} fields, got {}",
+                self.fields.len(),
+                values.len()
+            )));
+        }
+        for (field, v) in self.fields.iter().zip(values) {
+            if v.len() != field.count {
+                return Err(anyhow!(format!(
+                    "Field {:?} has COUNT {} but got {} values",
+                    field.name,
+                    field.count,
+                    v.len()
+                )));
+            }
+        }
+        for ((field, column), v) in self.fields.iter().zip(self.columns.iter_mut()).zip(values) {
+            for value in v {
+                match column {
+                    FieldData::F32(c) if is_packed_color(&field.name) => {
+                        c.push(f32::from_bits(*value as u32))
+                    }
+                    _ => column.push(*value),
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Builds a dynamic cloud holding the fields listed by `Point::fields`.
+    pub fn from_pointcloud<T>(pointcloud: &PointCloud<T>) -> Result<DynamicPointCloud>
+    where
+        T: Point,
+    {
+        let mut cloud = DynamicPointCloud::new(T::fields());
+        for point in pointcloud.data.iter() {
+            let values = cloud
+                .fields
+                .iter()
+                .map(|f| point.field_values(&f.name))
+                .collect::<Result<Vec<_>>>()?;
+            cloud.push_point(&values)?;
+        }
+        cloud.width = pointcloud.width;
+        cloud.height = pointcloud.height;
+        Ok(cloud)
+    }
+    /// Converts into a typed cloud, dropping the fields the point type does not carry.
+    ///
+    /// Returns an error if a carried field cannot be set, e.g. for a mismatched `count`.
+    pub fn to_pointcloud<T>(&self) -> Result<PointCloud<T>>
+    where
+        T: Point + Default,
+        <T as Point>::Item: FloatData + NumAssign,
+    {
+        let target_fields = T::fields();
+        let carried = self
+            .fields
+            .iter()
+            .filter(|field| {
+                target_fields.iter().any(|f| {
+                    f.name == field.name
+                        || (is_packed_color(&f.name) && is_packed_color(&field.name))
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut pointcloud = PointCloud::<T>::new();
+        for i in 0..self.len() {
+            let mut data = T::default();
+            for field in carried.iter() {
+                data.set_field_values(&field.name, &self.field_values(i, &field.name)?)?;
+            }
+            pointcloud.add_data(data);
+        }
+        pointcloud.width = self.width;
+        pointcloud.height = self.height;
+        Ok(pointcloud)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcd::{read_pcd_dynamic_from_reader, write_pcd_dynamic_to_writer, PCDEncoding};
+    use crate::pointcloud::PointXYZ;
+
+    fn lidar_cloud() -> DynamicPointCloud {
+        let mut cloud = DynamicPointCloud::new(vec![
+            PointField::new("x", FieldType::F32),
+            PointField::new("y", FieldType::F32),
+            PointField::new("z", FieldType::F32),
+            PointField::new("ring", FieldType::U16),
+            PointField::new("time", FieldType::F64),
+        ]);
+        for i in 0..4 {
+            let t = i as f64;
+            cloud
+                .push_point(&[
+                    vec![t],
+                    vec![-t],
+                    vec![0.5],
+                    vec![i as f64 * 3.0],
+                    vec![t * 1e-7],
+                ])
+                .unwrap();
+        }
+        cloud
+    }
+
+    #[test]
+    fn pcd_round_trip() {
+        let cloud = lidar_cloud();
+        assert_eq!(cloud.len(), 4);
+        assert_eq!(
+            cloud.column("ring"),
+            Some(&FieldData::U16(vec![0, 3, 6, 9]))
+        );
+        for encoding in [
+            PCDEncoding::Ascii,
+            PCDEncoding::Binary,
+            PCDEncoding::BinaryCompressed,
+        ]
+        .iter()
+        {
+            let mut bytes = Vec::new();
+            write_pcd_dynamic_to_writer(&cloud, &mut bytes, *encoding).unwrap();
+            let loaded = read_pcd_dynamic_from_reader(&bytes[..]).unwrap();
+            assert_eq!(loaded.fields(), cloud.fields(), "{:?}", encoding);
+            assert_eq!(loaded.columns(), cloud.columns(), "{:?}", encoding);
+            assert_eq!((loaded.width, loaded.height), (4, 1));
+        }
+    }
+
+    #[test]
+    fn field_values_out_of_range() {
+        let cloud = lidar_cloud();
+        assert_eq!(cloud.field_values(3, "ring").unwrap(), vec![9.0]);
+        assert!(cloud.field_values(4, "ring").is_err());
+        assert!(cloud.field_values(0, "intensity").is_err());
+    }
+
+    #[test]
+    fn to_pointcloud() {
+        let pointcloud: PointCloud<PointXYZ<f32>> = lidar_cloud().to_pointcloud().unwrap();
+        assert_eq!(pointcloud.len(), 4);
+        assert_eq!(pointcloud.item(2).point[1], -2.0);
+
+        // A carried field with another count cannot be set.
+        let mut cloud = DynamicPointCloud::new(vec![
+            PointField::new("y", FieldType::F32),
+            PointField {
+                name: "x".to_string(),
+                datatype: FieldType::F32,
+                count: 2,
+            },
+        ]);
+        cloud.push_point(&[vec![1.0], vec![2.0, 3.0]]).unwrap();
+        assert!(cloud.to_pointcloud::<PointXYZ<f32>>().is_err());
+    }
+}
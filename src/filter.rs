@@ -16,6 +16,9 @@ where
     T: Point + Default + Add<Output = T> + Copy + Div<<T as Point>::Item, Output = T>,
     <T as Point>::Item: FloatData + RealField + FromPrimitive + AsPrimitive<u32>,
 {
+    /// Downsamples the cloud by averaging the points in each voxel.
+    ///
+    /// The result is unorganized.
     pub fn voxel_grid_filter(&self, voxel_size: <T as Point>::Item) -> Result<PointCloud<T>> {
         if self.len() <= 2 {
             return Err(anyhow::anyhow!(
@@ -31,17 +34,17 @@ where
             let key = (p.xyz() - min_bound) / voxel_size;
             voxel_map
                 .entry((key[0].as_(), key[1].as_(), key[2].as_()))
-                .or_insert(Vec::new())
+                .or_default()
                 .push(*p);
         }
         let voxelized_data = voxel_map
-            .into_iter()
-            .map(|(_k, v)| {
+            .into_values()
+            .map(|v| {
                 let n = v.len();
                 if n == 1 {
                     v[0]
                 } else {
-                    v[1..].into_iter().fold(v[0], |s, &e| s + e)
+                    v[1..].iter().fold(v[0], |s, &e| s + e)
                         / <T as Point>::Item::from_usize(n).unwrap()
                 }
             })
@@ -49,16 +52,43 @@ where
         Ok(PointCloud::<T> {
             data: voxelized_data,
             width: 1,
+            height: 1,
             _marker: PhantomData,
         })
     }
 }
 
+impl<T> PointCloud<T>
+where
+    T: Point + Clone,
+{
+    /// Removes points whose position is not finite, such as the NaN placeholders of
+    /// clouds read with `read_pcd_organized`.
+    ///
+    /// The result is unorganized.
+    pub fn remove_non_finite_points(&self) -> PointCloud<T> {
+        PointCloud::<T> {
+            data: self
+                .data
+                .iter()
+                .filter(|p| p.xyz().iter().all(|v| v.is_finite()))
+                .cloned()
+                .collect(),
+            width: 1,
+            height: 1,
+            _marker: PhantomData,
+        }
+    }
+}
+
 impl<T> PointCloud<T>
 where
     T: Point + Default + Copy + Default + KdPoint<Scalar = <T as Point>::Item>,
     <T as Point>::Item: FloatData + RealField,
 {
+    /// Removes points with `neighbor_counts` or fewer neighbors within `radius`.
+    ///
+    /// The result is unorganized.
     pub fn remove_radius_outliers(
         &self,
         radius: <T as Point>::Item,
@@ -78,6 +108,7 @@ where
         PointCloud::<T> {
             data: filtered_data,
             width: 1,
+            height: 1,
             _marker: PhantomData,
         }
     }
@@ -88,6 +119,9 @@ where
     T: Point + Default + Copy + Default + KdPoint<Scalar = <T as Point>::Item>,
     <T as Point>::Item: FloatData + RealField + Sum,
 {
+    /// Removes points whose mean neighbor distance is far from the cloud average.
+    ///
+    /// The result is unorganized.
     pub fn remove_statistical_outliers(
         &self,
         k_neighbors: usize,
@@ -126,6 +160,7 @@ where
         PointCloud::<T> {
             data: filtered_data,
             width: 1,
+            height: 1,
             _marker: PhantomData,
         }
     }
@@ -1,9 +1,11 @@
 extern crate nalgebra as na;
+use super::pointcloud::{
+    FloatData, Point, PointCloud, PointXYZ, PointXYZI, PointXYZIRT, PointXYZL, PointXYZNormal,
+};
 use kd_tree::{ItemAndDistance, KdPoint, KdTree};
 use num_traits::NumAssign;
 use ordered_float::OrderedFloat;
 use typenum;
-use super::pointcloud::{FloatData, Point, PointCloud, PointXYZ, PointXYZNormal};
 
 impl<T> KdPoint for PointXYZ<T>
 where
@@ -28,6 +30,39 @@ where
     }
 }
 
+impl<T> KdPoint for PointXYZI<T>
+where
+    T: FloatData + NumAssign,
+{
+    type Scalar = T;
+    type Dim = typenum::U3;
+    fn at(&self, k: usize) -> T {
+        self.point[k]
+    }
+}
+
+impl<T> KdPoint for PointXYZL<T>
+where
+    T: FloatData + NumAssign,
+{
+    type Scalar = T;
+    type Dim = typenum::U3;
+    fn at(&self, k: usize) -> T {
+        self.point[k]
+    }
+}
+
+impl<T> KdPoint for PointXYZIRT<T>
+where
+    T: FloatData + NumAssign,
+{
+    type Scalar = T;
+    type Dim = typenum::U3;
+    fn at(&self, k: usize) -> T {
+        self.point[k]
+    }
+}
+
 impl<T> PointCloud<T>
 where
     T: Point + Copy + KdPoint<Scalar = <T as Point>::Item>,
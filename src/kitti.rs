@@ -0,0 +1,130 @@
+use super::pointcloud::{Point, PointCloud};
+use anyhow::{anyhow, Result};
+use core::convert::TryInto;
+use num_traits::NumAssign;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+const RECORD_SIZE: usize = 16;
+
+/// Reads a KITTI Velodyne scan: flat little-endian `x y z intensity` float32 records.
+///
+/// Intensity is kept when the point type has an `intensity` field.
+pub fn read_kitti_bin<T>(filename: &str) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_kitti_bin_from_reader(BufReader::new(File::open(filename)?))
+}
+
+/// Same as `read_kitti_bin`, reading from any source.
+pub fn read_kitti_bin_from_reader<T, R>(mut reader: R) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+    R: Read,
+{
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() % RECORD_SIZE != 0 {
+        return Err(anyhow!(format!(
+            "KITTI scan size {} is not a multiple of {} bytes",
+            bytes.len(),
+            RECORD_SIZE
+        )));
+    }
+    let mut pointcloud = PointCloud::<T>::new();
+    pointcloud.data.reserve(bytes.len() / RECORD_SIZE);
+    for record in bytes.chunks_exact(RECORD_SIZE) {
+        let mut values = [0.0f32; 4];
+        for (v, b) in values.iter_mut().zip(record.chunks_exact(4)) {
+            *v = f32::from_le_bytes(b.try_into()?);
+        }
+        let mut data = T::default();
+        data.set_field_value("x", values[0] as f64)?;
+        data.set_field_value("y", values[1] as f64)?;
+        data.set_field_value("z", values[2] as f64)?;
+        // Point types without intensity drop it.
+        let _ = data.set_field_value("intensity", values[3] as f64);
+        pointcloud.add_data(data);
+    }
+    Ok(pointcloud)
+}
+
+/// Writes a point cloud as a KITTI Velodyne scan.
+///
+/// Intensity is written as 0 when the point type has no `intensity` field.
+pub fn write_kitti_bin<T>(pointcloud: &PointCloud<T>, filename: &str) -> Result<()>
+where
+    T: Point,
+{
+    let mut writer = BufWriter::new(File::create(filename)?);
+    write_kitti_bin_to_writer(pointcloud, &mut writer)
+}
+
+/// Same as `write_kitti_bin`, writing to any writer.
+pub fn write_kitti_bin_to_writer<T, W>(pointcloud: &PointCloud<T>, writer: &mut W) -> Result<()>
+where
+    T: Point,
+    W: Write,
+{
+    for point in pointcloud.data.iter() {
+        let intensity = point.field_value("intensity").unwrap_or(0.0);
+        for value in [
+            point.field_value("x")?,
+            point.field_value("y")?,
+            point.field_value("z")?,
+            intensity,
+        ]
+        .iter()
+        {
+            writer.write_all(&(*value as f32).to_le_bytes())?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::{PointXYZ, PointXYZI};
+    use nalgebra::Vector3;
+
+    #[test]
+    fn round_trip_with_intensity() {
+        let mut cloud = PointCloud::<PointXYZI<f32>>::new();
+        for (i, p) in [[1.0, -2.0, 3.5], [0.25, 0.0, -7.0]].iter().enumerate() {
+            cloud.add_data(PointXYZI {
+                point: Vector3::new(p[0], p[1], p[2]),
+                intensity: 0.5 * i as f32,
+            });
+        }
+        let mut bytes = Vec::new();
+        write_kitti_bin_to_writer(&cloud, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), 2 * RECORD_SIZE);
+        assert_eq!(&bytes[16..20], &0.25f32.to_le_bytes());
+        assert_eq!(&bytes[28..32], &0.5f32.to_le_bytes());
+
+        let read = read_kitti_bin_from_reader::<PointXYZI<f32>, _>(&bytes[..]).unwrap();
+        assert_eq!(read.len(), 2);
+        for (a, b) in read.data.iter().zip(cloud.data.iter()) {
+            assert_eq!(a.point, b.point);
+            assert_eq!(a.intensity, b.intensity);
+        }
+
+        // Point types without intensity drop it on read and write 0.
+        let xyz = read_kitti_bin_from_reader::<PointXYZ<f32>, _>(&bytes[..]).unwrap();
+        assert_eq!(xyz.data[1].point, cloud.data[1].point);
+        let mut written = Vec::new();
+        write_kitti_bin_to_writer(&xyz, &mut written).unwrap();
+        assert_eq!(&written[28..32], &0.0f32.to_le_bytes());
+    }
+
+    #[test]
+    fn partial_record() {
+        let bytes = [0u8; RECORD_SIZE + 4];
+        assert!(read_kitti_bin_from_reader::<PointXYZ<f32>, _>(&bytes[..]).is_err());
+    }
+}
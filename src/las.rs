@@ -0,0 +1,542 @@
+use super::dynamic_pointcloud::DynamicPointCloud;
+use super::pointcloud::{pack_rgb, FieldType, Point, PointCloud, PointField};
+use anyhow::{anyhow, Result};
+use core::convert::{TryFrom, TryInto};
+use nalgebra::Vector3;
+use num_traits::NumAssign;
+
+/// Variable length record of a LAS file.
+#[derive(Clone, Debug)]
+pub struct LasVlr {
+    pub user_id: String,
+    pub record_id: u16,
+    pub description: String,
+    pub data: Vec<u8>,
+}
+
+/// Header of a LAS or LAZ file.
+#[derive(Clone, Debug)]
+pub struct LasHeader {
+    pub version: (u8, u8),
+    pub system_identifier: String,
+    pub generating_software: String,
+    /// Point data record format, 0 to 10.
+    pub point_format: u8,
+    pub point_record_length: usize,
+    pub number_of_points: u64,
+    pub offset_to_point_data: usize,
+    pub scale: Vector3<f64>,
+    pub offset: Vector3<f64>,
+    pub min_bound: Vector3<f64>,
+    pub max_bound: Vector3<f64>,
+    /// Whether the point data is LAZ compressed.
+    pub compressed: bool,
+    /// Variable length records, followed by the extended ones of LAS 1.4.
+    pub vlrs: Vec<LasVlr>,
+}
+
+impl LasHeader {
+    fn projection_vlr(&self, record_id: u16) -> Option<&LasVlr> {
+        self.vlrs
+            .iter()
+            .find(|v| v.user_id == "LASF_Projection" && v.record_id == record_id)
+    }
+    /// Coordinate system stored as OGC WKT, if any.
+    pub fn wkt(&self) -> Option<String> {
+        self.projection_vlr(2112)
+            .map(|v| c_string(&v.data))
+            .filter(|s| !s.is_empty())
+    }
+    /// GeoTIFF keys stored in the GeoKeyDirectoryTag record, if any.
+    pub fn geo_keys(&self) -> Option<Vec<u16>> {
+        self.projection_vlr(34735).map(|v| {
+            v.data
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect()
+        })
+    }
+    /// EPSG code of the projected, or else geographic, coordinate system declared by
+    /// the GeoTIFF keys.
+    pub fn epsg(&self) -> Option<u16> {
+        let keys = self.geo_keys()?;
+        let n_keys = *keys.get(3)? as usize;
+        let entries = keys.get(4..(4 + 4 * n_keys))?;
+        let find = |key_id: u16| {
+            entries
+                .chunks_exact(4)
+                .find(|e| e[0] == key_id && e[1] == 0 && e[3] != 32767)
+                .map(|e| e[3])
+        };
+        // ProjectedCSTypeGeoKey, then GeographicTypeGeoKey.
+        find(3072).or_else(|| find(2048))
+    }
+    fn has_gps_time(&self) -> bool {
+        !matches!(self.point_format, 0 | 2)
+    }
+    fn has_rgb(&self) -> bool {
+        matches!(self.point_format, 2 | 3 | 5 | 7 | 8 | 10)
+    }
+    fn has_nir(&self) -> bool {
+        matches!(self.point_format, 8 | 10)
+    }
+    fn is_extended(&self) -> bool {
+        self.point_format >= 6
+    }
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+/// Little-endian view over the bytes of a LAS file.
+struct LasBytes<'a>(&'a [u8]);
+
+impl<'a> LasBytes<'a> {
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.0.get(offset..end))
+            .ok_or_else(|| anyhow!(format!("Truncated LAS data at offset {}", offset)))
+    }
+    fn u8(&self, offset: usize) -> Result<u8> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+    fn u16(&self, offset: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.slice(offset, 2)?.try_into()?))
+    }
+    fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.slice(offset, 4)?.try_into()?))
+    }
+    fn u64(&self, offset: usize) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.slice(offset, 8)?.try_into()?))
+    }
+    fn f64(&self, offset: usize) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.slice(offset, 8)?.try_into()?))
+    }
+    fn vector3(&self, offset: usize) -> Result<Vector3<f64>> {
+        Ok(Vector3::new(
+            self.f64(offset)?,
+            self.f64(offset + 8)?,
+            self.f64(offset + 16)?,
+        ))
+    }
+}
+
+fn parse_header(bytes: &[u8]) -> Result<LasHeader> {
+    let b = LasBytes(bytes);
+    if b.slice(0, 4)? != b"LASF" {
+        return Err(anyhow!("Missing LASF signature"));
+    }
+    let version = (b.u8(24)?, b.u8(25)?);
+    if version.0 != 1 || version.1 > 4 {
+        return Err(anyhow!(format!(
+            "Unsupported LAS version {}.{}",
+            version.0, version.1
+        )));
+    }
+    let header_size = b.u16(94)? as usize;
+    let offset_to_point_data = b.u32(96)? as usize;
+    let n_vlrs = b.u32(100)? as usize;
+    let format_byte = b.u8(104)?;
+    // LAZ sets bit 7 (and historically bit 6) of the point format.
+    let compressed = format_byte & 0xc0 != 0;
+    let point_format = format_byte & 0x3f;
+    let point_record_length = b.u16(105)? as usize;
+    let mut number_of_points = b.u32(107)? as u64;
+    let (max_x, min_x) = (b.f64(179)?, b.f64(187)?);
+    let (max_y, min_y) = (b.f64(195)?, b.f64(203)?);
+    let (max_z, min_z) = (b.f64(211)?, b.f64(219)?);
+    if version.1 >= 4 && header_size >= 255 {
+        let n = b.u64(247)?;
+        if n > 0 {
+            number_of_points = n;
+        }
+    }
+
+    // Offsets come from the file, so every record is sliced out before reading its
+    // fields: a successful slice guarantees that `offset + length` fits in the input.
+    let mut vlrs = Vec::with_capacity(n_vlrs.min(1024));
+    let mut offset = header_size;
+    for _ in 0..n_vlrs {
+        let record = LasBytes(b.slice(offset, 54)?);
+        let length = record.u16(20)? as usize;
+        vlrs.push(LasVlr {
+            user_id: c_string(record.slice(2, 16)?),
+            record_id: record.u16(18)?,
+            description: c_string(record.slice(22, 32)?),
+            data: b.slice(offset + 54, length)?.to_vec(),
+        });
+        offset += 54 + length;
+    }
+    if version.1 >= 4 && header_size >= 247 {
+        let evlr_offset = b.u64(235)?;
+        let n_evlrs = b.u32(243)? as usize;
+        let mut offset = usize::try_from(evlr_offset)
+            .map_err(|_| anyhow!(format!("Truncated LAS data at offset {}", evlr_offset)))?;
+        for _ in 0..(if offset > 0 { n_evlrs } else { 0 }) {
+            let record = LasBytes(b.slice(offset, 60)?);
+            let length = record.u64(20)?;
+            let length = usize::try_from(length)
+                .map_err(|_| anyhow!(format!("Truncated LAS data at offset {}", offset)))?;
+            vlrs.push(LasVlr {
+                user_id: c_string(record.slice(2, 16)?),
+                record_id: record.u16(18)?,
+                description: c_string(record.slice(28, 32)?),
+                data: b.slice(offset + 60, length)?.to_vec(),
+            });
+            offset += 60 + length;
+        }
+    }
+
+    Ok(LasHeader {
+        version,
+        system_identifier: c_string(b.slice(26, 32)?),
+        generating_software: c_string(b.slice(58, 32)?),
+        point_format,
+        point_record_length,
+        number_of_points,
+        offset_to_point_data,
+        scale: b.vector3(131)?,
+        offset: b.vector3(155)?,
+        min_bound: Vector3::new(min_x, min_y, min_z),
+        max_bound: Vector3::new(max_x, max_y, max_z),
+        compressed,
+        vlrs,
+    })
+}
+
+#[cfg(feature = "laz")]
+fn decompress_points(bytes: &[u8], header: &LasHeader) -> Result<Vec<u8>> {
+    use laz::{LasZipDecompressor, LazVlr};
+    let vlr = header
+        .vlrs
+        .iter()
+        .find(|v| v.user_id == LazVlr::USER_ID && v.record_id == LazVlr::RECORD_ID)
+        .ok_or_else(|| anyhow!("Missing laszip VLR in LAZ file"))?;
+    let vlr = LazVlr::from_buffer(&vlr.data).map_err(|e| anyhow!(e.to_string()))?;
+    let source = std::io::Cursor::new(
+        bytes
+            .get(header.offset_to_point_data..)
+            .ok_or_else(|| anyhow!("Truncated LAZ data"))?,
+    );
+    let mut decompressor =
+        LasZipDecompressor::new(source, vlr).map_err(|e| anyhow!(e.to_string()))?;
+    let data_size = (header.number_of_points as usize)
+        .checked_mul(header.point_record_length)
+        .ok_or_else(|| anyhow!("Invalid LAS point count"))?;
+    // The point count comes from the header, so the output grows with what actually
+    // decompresses instead of being allocated upfront.
+    let chunk_size = header.point_record_length.max(1) * 4096;
+    let mut points = Vec::new();
+    while points.len() < data_size {
+        let start = points.len();
+        points.resize(data_size.min(start + chunk_size), 0);
+        decompressor.decompress_many(&mut points[start..])?;
+    }
+    Ok(points)
+}
+
+#[cfg(not(feature = "laz"))]
+fn decompress_points(_bytes: &[u8], _header: &LasHeader) -> Result<Vec<u8>> {
+    Err(anyhow!("Reading LAZ files requires the `laz` feature"))
+}
+
+fn las_fields(header: &LasHeader) -> Vec<PointField> {
+    let mut fields = vec![
+        PointField::new("x", FieldType::F64),
+        PointField::new("y", FieldType::F64),
+        PointField::new("z", FieldType::F64),
+        PointField::new("intensity", FieldType::U16),
+        PointField::new("return_number", FieldType::U8),
+        PointField::new("number_of_returns", FieldType::U8),
+        PointField::new("classification", FieldType::U8),
+        PointField::new("scan_angle", FieldType::F32),
+        PointField::new("user_data", FieldType::U8),
+        PointField::new("point_source_id", FieldType::U16),
+    ];
+    if header.has_gps_time() {
+        fields.push(PointField::new("gps_time", FieldType::F64));
+    }
+    if header.has_rgb() {
+        fields.push(PointField::new("rgb", FieldType::F32));
+        fields.push(PointField::new("red", FieldType::U16));
+        fields.push(PointField::new("green", FieldType::U16));
+        fields.push(PointField::new("blue", FieldType::U16));
+    }
+    if header.has_nir() {
+        fields.push(PointField::new("nir", FieldType::U16));
+    }
+    fields
+}
+
+fn parse_points(bytes: &[u8], header: &LasHeader) -> Result<DynamicPointCloud> {
+    if header.point_format > 10 {
+        return Err(anyhow!(format!(
+            "Unsupported LAS point format {}",
+            header.point_format
+        )));
+    }
+    let decompressed;
+    let points = if header.compressed {
+        decompressed = decompress_points(bytes, header)?;
+        &decompressed[..]
+    } else {
+        LasBytes(bytes).slice(
+            header.offset_to_point_data,
+            (header.number_of_points as usize)
+                .checked_mul(header.point_record_length)
+                .ok_or_else(|| anyhow!("Invalid LAS point count"))?,
+        )?
+    };
+    let records = points
+        .chunks_exact(header.point_record_length.max(1))
+        .map(LasBytes)
+        .collect::<Vec<_>>();
+
+    // Colors are meant to be scaled to 16 bits, but many writers store 8-bit values.
+    let color_offset = match header.point_format {
+        2 => 20,
+        3 | 5 => 28,
+        _ => 30,
+    };
+    let mut color_shift = 0;
+    if header.has_rgb() {
+        for r in records.iter() {
+            for c in 0..3 {
+                if r.u16(color_offset + 2 * c)? > 255 {
+                    color_shift = 8;
+                }
+            }
+        }
+    }
+
+    let mut cloud = DynamicPointCloud::new(las_fields(header));
+    let mut values = Vec::with_capacity(cloud.fields().len());
+    for r in records.iter() {
+        values.clear();
+        for (i, axis) in [0, 4, 8].iter().enumerate() {
+            let raw = i32::from_le_bytes(r.slice(*axis, 4)?.try_into()?) as f64;
+            values.push(vec![raw * header.scale[i] + header.offset[i]]);
+        }
+        values.push(vec![r.u16(12)? as f64]);
+        let returns = r.u8(14)?;
+        if header.is_extended() {
+            values.push(vec![(returns & 0x0f) as f64]);
+            values.push(vec![(returns >> 4) as f64]);
+            values.push(vec![r.u8(16)? as f64]);
+            let angle = i16::from_le_bytes(r.slice(18, 2)?.try_into()?);
+            values.push(vec![angle as f64 * 0.006]);
+            values.push(vec![r.u8(17)? as f64]);
+            values.push(vec![r.u16(20)? as f64]);
+            values.push(vec![r.f64(22)?]);
+        } else {
+            values.push(vec![(returns & 0x07) as f64]);
+            values.push(vec![((returns >> 3) & 0x07) as f64]);
+            values.push(vec![(r.u8(15)? & 0x1f) as f64]);
+            values.push(vec![r.u8(16)? as i8 as f64]);
+            values.push(vec![r.u8(17)? as f64]);
+            values.push(vec![r.u16(18)? as f64]);
+            if header.has_gps_time() {
+                values.push(vec![r.f64(20)?]);
+            }
+        }
+        if header.has_rgb() {
+            let rgb = [
+                r.u16(color_offset)?,
+                r.u16(color_offset + 2)?,
+                r.u16(color_offset + 4)?,
+            ];
+            values.push(vec![pack_rgb([
+                (rgb[0] >> color_shift) as u8,
+                (rgb[1] >> color_shift) as u8,
+                (rgb[2] >> color_shift) as u8,
+            ]) as f64]);
+            values.extend(rgb.iter().map(|c| vec![*c as f64]));
+        }
+        if header.has_nir() {
+            values.push(vec![r.u16(color_offset + 6)? as f64]);
+        }
+        cloud.push_point(&values)?;
+    }
+    Ok(cloud)
+}
+
+/// Reads the header of a LAS or LAZ file, including its bounds and CRS records.
+pub fn read_las_header(filename: &str) -> Result<LasHeader> {
+    parse_header(&std::fs::read(filename)?)
+}
+
+/// Reads every attribute of a LAS file into a `DynamicPointCloud`.
+///
+/// Positions are scaled and offset into `x`, `y` and `z`. Intensity, returns,
+/// classification, scan angle (in degrees), user data, point source id, GPS time,
+/// colors and NIR are stored as fields when the point format carries them. Colors are
+/// also provided as a packed `rgb` field. LAZ files are supported with the `laz` feature.
+pub fn read_las_dynamic(filename: &str) -> Result<DynamicPointCloud> {
+    read_las_dynamic_from_bytes(&std::fs::read(filename)?)
+}
+
+/// Same as `read_las_dynamic`, reading the file content from memory.
+pub fn read_las_dynamic_from_bytes(bytes: &[u8]) -> Result<DynamicPointCloud> {
+    let header = parse_header(bytes)?;
+    parse_points(bytes, &header)
+}
+
+/// Reads a LAS or LAZ file into a typed cloud, as described in `read_las_dynamic`.
+pub fn read_las<T>(filename: &str) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_las_dynamic(filename)?.to_pointcloud()
+}
+
+/// Same as `read_las`, reading the file content from memory.
+pub fn read_las_from_bytes<T>(bytes: &[u8]) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_las_dynamic_from_bytes(bytes)?.to_pointcloud()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::PointXYZI;
+
+    /// Header of a LAS 1.2 (227 bytes) or 1.4 (375 bytes) file without records.
+    fn header_bytes(minor: u8, point_format: u8, record_length: u16, n_points: u32) -> Vec<u8> {
+        let size: u16 = if minor >= 4 { 375 } else { 227 };
+        let mut b = vec![0u8; size as usize];
+        b[0..4].copy_from_slice(b"LASF");
+        b[24] = 1;
+        b[25] = minor;
+        b[94..96].copy_from_slice(&size.to_le_bytes());
+        b[96..100].copy_from_slice(&(size as u32).to_le_bytes());
+        b[104] = point_format;
+        b[105..107].copy_from_slice(&record_length.to_le_bytes());
+        b[107..111].copy_from_slice(&n_points.to_le_bytes());
+        for i in 0..3 {
+            b[131 + 8 * i..139 + 8 * i].copy_from_slice(&0.01f64.to_le_bytes());
+            b[155 + 8 * i..163 + 8 * i].copy_from_slice(&(i as f64 * 100.0).to_le_bytes());
+        }
+        b
+    }
+
+    fn push_vlr(b: &mut Vec<u8>, user_id: &str, record_id: u16, data: &[u8]) {
+        let mut record = vec![0u8; 54];
+        record[2..2 + user_id.len()].copy_from_slice(user_id.as_bytes());
+        record[18..20].copy_from_slice(&record_id.to_le_bytes());
+        record[20..22].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        b.extend(record);
+        b.extend(data);
+        let n_vlrs = u32::from_le_bytes(b[100..104].try_into().unwrap()) + 1;
+        b[100..104].copy_from_slice(&n_vlrs.to_le_bytes());
+        let offset = b.len() as u32;
+        b[96..100].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    /// Records of point format 0.
+    fn format0_points() -> Vec<u8> {
+        let mut points = Vec::new();
+        for (xyz, intensity) in [([100i32, 200, 300], 7u16), ([-100, 0, 50], 1000)].iter() {
+            let mut record = vec![0u8; 20];
+            for (i, v) in xyz.iter().enumerate() {
+                record[4 * i..4 * i + 4].copy_from_slice(&v.to_le_bytes());
+            }
+            record[12..14].copy_from_slice(&intensity.to_le_bytes());
+            record[14] = 0x11;
+            record[15] = 2;
+            points.extend(record);
+        }
+        points
+    }
+
+    fn check_points(cloud: &PointCloud<PointXYZI<f64>>) {
+        assert_eq!(cloud.len(), 2);
+        let expected = [([1.0, 102.0, 203.0], 7.0), ([-1.0, 100.0, 200.5], 1000.0)];
+        for (p, (xyz, intensity)) in cloud.data.iter().zip(expected.iter()) {
+            assert!((p.point.x - xyz[0]).abs() < 1e-9);
+            assert!((p.point.y - xyz[1]).abs() < 1e-9);
+            assert!((p.point.z - xyz[2]).abs() < 1e-9);
+            assert_eq!(p.intensity, *intensity);
+        }
+    }
+
+    #[test]
+    fn small_file() {
+        let mut bytes = header_bytes(2, 0, 20, 2);
+        push_vlr(&mut bytes, "LASF_Projection", 2112, b"PROJCS[\"test\"]\0");
+        bytes.extend(format0_points());
+
+        let header = parse_header(&bytes).unwrap();
+        assert_eq!(header.version, (1, 2));
+        assert_eq!(header.wkt().as_deref(), Some("PROJCS[\"test\"]"));
+        let cloud = read_las_dynamic_from_bytes(&bytes).unwrap();
+        let classification = cloud.field_values(1, "classification").unwrap();
+        assert_eq!(classification, vec![2.0]);
+        check_points(&read_las_from_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn truncated_file() {
+        let mut bytes = header_bytes(2, 0, 20, 2);
+        push_vlr(&mut bytes, "LASF_Projection", 2112, b"PROJCS[\"test\"]\0");
+        bytes.extend(format0_points());
+
+        for len in &[200, 240, bytes.len() - 5] {
+            let err = read_las_dynamic_from_bytes(&bytes[..*len]).unwrap_err();
+            assert!(err.to_string().starts_with("Truncated"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn evlr_offset_overflow() {
+        let mut bytes = header_bytes(4, 0, 20, 0);
+        bytes[235..243].copy_from_slice(&0xffff_ffff_ffff_fffau64.to_le_bytes());
+        bytes[243..247].copy_from_slice(&1u32.to_le_bytes());
+        let err = parse_header(&bytes).unwrap_err();
+        assert!(err.to_string().starts_with("Truncated"), "{}", err);
+
+        // A record that fits but whose data length runs past the end of the input.
+        bytes[235..243].copy_from_slice(&375u64.to_le_bytes());
+        let mut record = vec![0u8; 60];
+        record[20..28].copy_from_slice(&u64::MAX.to_le_bytes());
+        bytes.extend(record);
+        let err = parse_header(&bytes).unwrap_err();
+        assert!(err.to_string().starts_with("Truncated"), "{}", err);
+    }
+
+    #[cfg(feature = "laz")]
+    #[test]
+    fn small_laz_file() {
+        use laz::{LasZipCompressor, LazItemRecordBuilder, LazVlr};
+        let vlr = LazVlr::from_laz_items(
+            LazItemRecordBuilder::default_for_point_format_id(0, 0).unwrap(),
+        );
+        let mut vlr_data = Vec::new();
+        vlr.write_to(&mut vlr_data).unwrap();
+        let mut compressor = LasZipCompressor::new(std::io::Cursor::new(Vec::new()), vlr).unwrap();
+        compressor.compress_many(&format0_points()).unwrap();
+        compressor.done().unwrap();
+        let compressed = compressor.into_inner().into_inner();
+
+        let mut bytes = header_bytes(2, 0x80, 20, 2);
+        push_vlr(&mut bytes, LazVlr::USER_ID, LazVlr::RECORD_ID, &vlr_data);
+        bytes.extend(&compressed);
+        check_points(&read_las_from_bytes(&bytes).unwrap());
+
+        // A huge point count with little data fails instead of allocating upfront.
+        let mut huge = bytes.clone();
+        huge[25] = 4;
+        huge.splice(227..227, vec![0u8; 375 - 227]);
+        huge[94..96].copy_from_slice(&375u16.to_le_bytes());
+        let offset = u32::from_le_bytes(huge[96..100].try_into().unwrap()) + 148;
+        huge[96..100].copy_from_slice(&offset.to_le_bytes());
+        huge[247..255].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert!(read_las_dynamic_from_bytes(&huge).is_err());
+    }
+}
@@ -1,10 +1,176 @@
+pub mod camera;
+mod dynamic_pointcloud;
 pub mod filter;
 pub mod kdtree;
+mod kitti;
+mod las;
+pub mod mesh;
 pub mod normal;
+mod npy;
+pub mod odometry;
 mod pcd;
+mod ply;
 mod pointcloud;
+mod pointcloud2;
 pub mod rgbdimage;
+pub mod tsdf;
 pub mod visualization;
+mod xyz;
 
+pub use self::dynamic_pointcloud::*;
+pub use self::kitti::*;
+pub use self::las::*;
+pub use self::npy::*;
 pub use self::pcd::*;
+pub use self::ply::*;
 pub use self::pointcloud::*;
+pub use self::pointcloud2::*;
+pub use self::xyz::*;
+
+/// Derives `Point`, and `Color` / `Normal` when marked, for a user-defined point type.
+///
+/// Filters average floating point fields, while integer fields keep the value of the
+/// first point of each group.
+///
+/// ```
+/// use nalgebra::Vector3;
+/// use siskin::PointCloud;
+///
+/// #[derive(Clone, Copy, Default, Debug, siskin::Point)]
+/// struct PointXYZL {
+///     #[siskin(xyz)]
+///     point: Vector3<f32>,
+///     #[siskin(field)]
+///     label: u32,
+/// }
+///
+/// let mut pointcloud = PointCloud::<PointXYZL>::new();
+/// pointcloud.add_data(PointXYZL {
+///     point: Vector3::new(1.0, 2.0, 3.0),
+///     label: 7,
+/// });
+/// ```
+pub use siskin_derive::Point;
+
+/// Items used by the code generated by `#[derive(Point)]`.
+#[doc(hidden)]
+pub mod __private {
+    pub use anyhow;
+    pub use kd_tree;
+    pub use nalgebra;
+    pub use num_traits;
+    pub use typenum;
+
+    use super::pointcloud::{self, Color, ColorData, FieldScalar, FloatData};
+    use num_traits::{NumCast, ToPrimitive};
+
+    pub fn to_f64<T: ToPrimitive>(value: T) -> anyhow::Result<f64> {
+        pointcloud::to_f64(value)
+    }
+
+    pub fn from_f64<T: NumCast>(value: f64) -> anyhow::Result<T> {
+        pointcloud::from_f64(value)
+    }
+
+    pub fn packed_color_value<C: Color>(point: &C) -> f64
+    where
+        <C as Color>::Item: ColorData,
+    {
+        pointcloud::packed_color_value(point)
+    }
+
+    pub fn transform_normal_vector<T: FloatData, U: FloatData>(
+        normal_matrix: &nalgebra::Matrix3<T>,
+        normal: &mut nalgebra::Vector3<U>,
+    ) {
+        pointcloud::transform_normal_vector(normal_matrix, normal)
+    }
+
+    pub fn set_packed_color<C: Color>(point: &mut C, value: f64)
+    where
+        <C as Color>::Item: ColorData,
+    {
+        pointcloud::set_packed_color(point, value)
+    }
+
+    /// Sums floating point values and keeps the left value of integers.
+    pub fn add_value<T: FieldScalar>(value: T, other: T) -> T {
+        if T::FIELD_TYPE.is_float() {
+            value + other
+        } else {
+            value
+        }
+    }
+
+    /// Divides floating point values and keeps integers unchanged.
+    pub fn div_value<T: FieldScalar, D: ToPrimitive>(value: T, divisor: D) -> T {
+        if !T::FIELD_TYPE.is_float() {
+            return value;
+        }
+        match (value.to_f64(), divisor.to_f64()) {
+            (Some(v), Some(d)) => T::from(v / d).unwrap_or(value),
+            _ => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    #[derive(Clone, Copy, Default, Debug, Point)]
+    #[siskin(crate = "crate")]
+    struct PointXYZIL {
+        #[siskin(xyz)]
+        point: Vector3<f32>,
+        #[siskin(field = "intensity")]
+        intensity: f32,
+        #[siskin(field)]
+        label: u32,
+        #[siskin(field)]
+        histogram: [u16; 2],
+    }
+
+    #[test]
+    fn derived_point() {
+        assert_eq!(
+            PointXYZIL::fields(),
+            vec![
+                PointField::new("x", FieldType::F32),
+                PointField::new("y", FieldType::F32),
+                PointField::new("z", FieldType::F32),
+                PointField::new("intensity", FieldType::F32),
+                PointField::new("label", FieldType::U32),
+                PointField {
+                    name: "histogram".to_string(),
+                    datatype: FieldType::U16,
+                    count: 2,
+                },
+            ]
+        );
+
+        let mut pointcloud = PointCloud::<PointXYZIL>::new();
+        for i in 0..4 {
+            pointcloud.add_data(PointXYZIL {
+                point: Vector3::new(0.01 * i as f32, 0.0, 0.0),
+                intensity: i as f32,
+                label: 7,
+                histogram: [i, 3],
+            });
+        }
+        let filtered = pointcloud.voxel_grid_filter(1.0).unwrap();
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered.item(0).label, 7);
+        assert_eq!(filtered.item(0).histogram, [0, 3]);
+        assert!((filtered.item(0).intensity - 1.5).abs() < 1e-6);
+
+        let mut bytes = Vec::new();
+        write_pcd_to_writer(&filtered, &mut bytes, PCDEncoding::Binary).unwrap();
+        let loaded: PointCloud<PointXYZIL> = read_pcd_from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.item(0).label, 7);
+        assert_eq!(loaded.item(0).histogram, [0, 3]);
+        assert_eq!(loaded.item(0).intensity, filtered.item(0).intensity);
+        assert_eq!(loaded.item(0).point, filtered.item(0).point);
+    }
+}
@@ -0,0 +1,34 @@
+use nalgebra::Vector3;
+
+/// An indexed triangle mesh with optional per-vertex colors in [0, 1].
+///
+/// Triangles are counter-clockwise when seen from the side their normal points to.
+#[derive(Clone, Debug, Default)]
+pub struct TriangleMesh {
+    pub vertices: Vec<Vector3<f64>>,
+    pub vertex_colors: Vec<Vector3<f32>>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl TriangleMesh {
+    pub fn new() -> TriangleMesh {
+        TriangleMesh::default()
+    }
+    pub fn has_vertex_colors(&self) -> bool {
+        !self.vertex_colors.is_empty() && self.vertex_colors.len() == self.vertices.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+    /// Unit normal of each triangle, or zero for degenerate triangles.
+    pub fn triangle_normals(&self) -> Vec<Vector3<f64>> {
+        self.triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                let n = (self.vertices[b] - self.vertices[a])
+                    .cross(&(self.vertices[c] - self.vertices[a]));
+                n.try_normalize(f64::EPSILON).unwrap_or_else(Vector3::zeros)
+            })
+            .collect()
+    }
+}
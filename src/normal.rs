@@ -12,6 +12,7 @@ where
         + KdPoint<Scalar = <T as Point>::Item>,
     <T as Point>::Item: FloatData + FromPrimitive + ComplexField + NumAssign,
 {
+    /// Estimates normals in place, preserving the organization of the cloud.
     pub fn compute_normals(&mut self, radius: <T as Point>::Item) {
         let kdtree = self.build_kdtree();
         for p in self.data.iter_mut() {
@@ -0,0 +1,499 @@
+use super::pointcloud::{pack_rgb, unpack_rgb, FieldType, Point, PointCloud};
+use anyhow::{anyhow, Result};
+use core::convert::TryInto;
+use num_traits::NumAssign;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+const NORMAL_FIELDS: [&str; 3] = ["normal_x", "normal_y", "normal_z"];
+
+/// Row-major 2D float array decoded from a `.npy` file.
+struct NpyArray {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+fn header_entry<'a>(header: &'a str, key: &str) -> Result<&'a str> {
+    let pattern = format!("'{}':", key);
+    let start = header
+        .find(&pattern)
+        .ok_or_else(|| anyhow!(format!("Missing {:?} in npy header", key)))?;
+    Ok(header[(start + pattern.len())..].trim_start())
+}
+
+fn parse_npy_header(header: &str) -> Result<(String, bool, Vec<usize>)> {
+    let descr = header_entry(header, "descr")?;
+    let descr = descr
+        .strip_prefix('\'')
+        .and_then(|s| s.split('\'').next())
+        .ok_or_else(|| anyhow!("Invalid descr in npy header"))?
+        .to_string();
+    let fortran_order = header_entry(header, "fortran_order")?.starts_with("True");
+    let shape = header_entry(header, "shape")?;
+    let shape = shape
+        .strip_prefix('(')
+        .and_then(|s| s.split(')').next())
+        .ok_or_else(|| anyhow!("Invalid shape in npy header"))?
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<usize>()
+                .map_err(|_| anyhow!(format!("Invalid npy dimension {:?}", s)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((descr, fortran_order, shape))
+}
+
+fn read_array<R: Read>(reader: &mut R) -> Result<NpyArray> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != NPY_MAGIC {
+        return Err(anyhow!("Missing NUMPY magic string"));
+    }
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        2 | 3 => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        v => return Err(anyhow!(format!("Unsupported npy version {}", v))),
+    };
+    let mut header = vec![0u8; header_len];
+    reader.read_exact(&mut header)?;
+    let (descr, fortran_order, shape) = parse_npy_header(&String::from_utf8_lossy(&header))?;
+    let (rows, cols) = match shape[..] {
+        [rows] => (rows, 1),
+        [rows, cols] => (rows, cols),
+        _ => {
+            return Err(anyhow!(format!(
+                "Expected a 2D npy array, found shape {:?}",
+                shape
+            )))
+        }
+    };
+    let (big_endian, size) = match &descr[..] {
+        "<f4" | "=f4" => (false, 4),
+        ">f4" => (true, 4),
+        "<f8" | "=f8" => (false, 8),
+        ">f8" => (true, 8),
+        _ => {
+            return Err(anyhow!(format!(
+                "Unsupported npy dtype {:?}, expected float32 or float64",
+                descr
+            )))
+        }
+    };
+
+    let n = rows
+        .checked_mul(cols)
+        .ok_or_else(|| anyhow!("Invalid npy shape"))?;
+    let data_size = n
+        .checked_mul(size)
+        .ok_or_else(|| anyhow!("Invalid npy shape"))?;
+    let mut bytes = Vec::new();
+    reader.take(data_size as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != data_size {
+        return Err(anyhow!(format!(
+            "npy data holds {} bytes, expected {}",
+            bytes.len(),
+            data_size
+        )));
+    }
+    let values = bytes.chunks_exact(size).map(|b| -> Result<f64> {
+        Ok(match (size, big_endian) {
+            (4, false) => f32::from_le_bytes(b.try_into()?) as f64,
+            (4, true) => f32::from_be_bytes(b.try_into()?) as f64,
+            (_, false) => f64::from_le_bytes(b.try_into()?),
+            (_, true) => f64::from_be_bytes(b.try_into()?),
+        })
+    });
+    let mut data = values.collect::<Result<Vec<_>>>()?;
+    if fortran_order && cols > 1 {
+        data = (0..n).map(|i| data[(i % cols) * rows + i / cols]).collect();
+    }
+    Ok(NpyArray { rows, cols, data })
+}
+
+fn write_array<W: Write>(
+    writer: &mut W,
+    rows: usize,
+    cols: usize,
+    datatype: FieldType,
+    values: &[f64],
+) -> Result<()> {
+    let descr = if datatype == FieldType::F64 {
+        "<f8"
+    } else {
+        "<f4"
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        descr, rows, cols
+    );
+    // The header ends with a newline and keeps the data 64-byte aligned.
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+    writer.write_all(NPY_MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for v in values.iter() {
+        if datatype == FieldType::F64 {
+            writer.write_all(&v.to_le_bytes())?;
+        } else {
+            writer.write_all(&(*v as f32).to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn has_field<T: Point>(name: &str) -> bool {
+    T::fields().iter().any(|f| f.name == name)
+}
+
+fn coordinate_type<T: Point>() -> FieldType {
+    T::fields()
+        .iter()
+        .find(|f| f.name == "x")
+        .map_or(FieldType::F32, |f| f.datatype)
+}
+
+fn points_array<T: Point>(pointcloud: &PointCloud<T>) -> Result<Vec<f64>> {
+    let mut values = Vec::with_capacity(pointcloud.data.len() * 3);
+    for point in pointcloud.data.iter() {
+        for name in ["x", "y", "z"].iter() {
+            values.push(point.field_value(name)?);
+        }
+    }
+    Ok(values)
+}
+
+fn colors_array<T: Point>(pointcloud: &PointCloud<T>) -> Result<Vec<f64>> {
+    let mut values = Vec::with_capacity(pointcloud.data.len() * 3);
+    for point in pointcloud.data.iter() {
+        let rgb = unpack_rgb(point.field_value("rgb")? as u32);
+        values.extend(rgb.iter().map(|c| *c as f64 / 255.0));
+    }
+    Ok(values)
+}
+
+fn normals_array<T: Point>(pointcloud: &PointCloud<T>) -> Result<Vec<f64>> {
+    let mut values = Vec::with_capacity(pointcloud.data.len() * 3);
+    for point in pointcloud.data.iter() {
+        for name in NORMAL_FIELDS.iter() {
+            values.push(point.field_value(name)?);
+        }
+    }
+    Ok(values)
+}
+
+fn set_colors<T: Point>(data: &mut T, values: &[f64]) -> Result<()> {
+    let mut rgb = [0u8; 3];
+    for (c, v) in rgb.iter_mut().zip(values) {
+        *c = (v * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+    data.set_field_value("rgb", pack_rgb(rgb) as f64)
+}
+
+fn set_normals<T: Point>(data: &mut T, values: &[f64]) -> Result<()> {
+    for (name, v) in NORMAL_FIELDS.iter().zip(values) {
+        data.set_field_value(name, *v)?;
+    }
+    Ok(())
+}
+
+/// Writes a point cloud as a 2D `.npy` array.
+///
+/// Each row holds `x y z`, followed by `r g b` in [0, 1] if the point type has colors,
+/// and by `nx ny nz` if it has normals, giving Nx3, Nx6 or Nx9 arrays. The dtype is
+/// float64 for `f64` points and float32 otherwise.
+pub fn write_npy<T>(pointcloud: &PointCloud<T>, filename: &str) -> Result<()>
+where
+    T: Point,
+{
+    let mut writer = BufWriter::new(File::create(filename)?);
+    write_npy_to_writer(pointcloud, &mut writer)
+}
+
+/// Same as `write_npy`, writing to any writer.
+pub fn write_npy_to_writer<T, W>(pointcloud: &PointCloud<T>, writer: &mut W) -> Result<()>
+where
+    T: Point,
+    W: Write,
+{
+    let mut arrays = vec![points_array(pointcloud)?];
+    if has_field::<T>("rgb") {
+        arrays.push(colors_array(pointcloud)?);
+    }
+    if has_field::<T>("normal_x") {
+        arrays.push(normals_array(pointcloud)?);
+    }
+    let n_points = pointcloud.data.len();
+    let mut values = Vec::with_capacity(n_points * 3 * arrays.len());
+    for i in 0..n_points {
+        for array in arrays.iter() {
+            values.extend_from_slice(&array[(i * 3)..(i * 3 + 3)]);
+        }
+    }
+    write_array(
+        writer,
+        n_points,
+        3 * arrays.len(),
+        coordinate_type::<T>(),
+        &values,
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a float32 or float64 `.npy` array of points.
+///
+/// Nx3 arrays hold positions and Nx9 arrays positions, colors in [0, 1] and normals.
+/// The last three columns of an Nx6 array are read as colors if the point type has
+/// colors, and as normals otherwise.
+pub fn read_npy<T>(filename: &str) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_npy_from_reader(BufReader::new(File::open(filename)?))
+}
+
+/// Same as `read_npy`, reading from any source.
+pub fn read_npy_from_reader<T, R>(mut reader: R) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+    R: Read,
+{
+    let array = read_array(&mut reader)?;
+    if ![3, 6, 9].contains(&array.cols) {
+        return Err(anyhow!(format!(
+            "Expected an Nx3, Nx6 or Nx9 array, found {}x{}",
+            array.rows, array.cols
+        )));
+    }
+    let colors_first = has_field::<T>("rgb");
+    let mut pointcloud = PointCloud::<T>::new();
+    for row in array.data.chunks_exact(array.cols) {
+        let mut data = T::default();
+        // Columns the point type does not carry are ignored.
+        match array.cols {
+            6 if colors_first => set_colors(&mut data, &row[3..6])?,
+            6 => {
+                let _ = set_normals(&mut data, &row[3..6]);
+            }
+            9 => {
+                let _ = set_colors(&mut data, &row[3..6]);
+                let _ = set_normals(&mut data, &row[6..9]);
+            }
+            _ => {}
+        }
+        data.set_field_value("x", row[0])?;
+        data.set_field_value("y", row[1])?;
+        data.set_field_value("z", row[2])?;
+        pointcloud.add_data(data);
+    }
+    Ok(pointcloud)
+}
+
+/// Writes a `.npz` bundle with Nx3 `points`, `colors` and `normals` arrays.
+///
+/// `colors` (in [0, 1]) and `normals` are only written if the point type carries them.
+pub fn write_npz<T>(pointcloud: &PointCloud<T>, filename: &str) -> Result<()>
+where
+    T: Point,
+{
+    write_npz_to_writer(pointcloud, BufWriter::new(File::create(filename)?))
+}
+
+/// Same as `write_npz`, writing to any seekable writer.
+pub fn write_npz_to_writer<T, W>(pointcloud: &PointCloud<T>, writer: W) -> Result<()>
+where
+    T: Point,
+    W: Write + Seek,
+{
+    let mut arrays = vec![("points", points_array(pointcloud)?)];
+    if has_field::<T>("rgb") {
+        arrays.push(("colors", colors_array(pointcloud)?));
+    }
+    if has_field::<T>("normal_x") {
+        arrays.push(("normals", normals_array(pointcloud)?));
+    }
+    let mut zip = zip::ZipWriter::new(writer);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, values) in arrays.iter() {
+        zip.start_file(format!("{}.npy", name), options)?;
+        write_array(
+            &mut zip,
+            pointcloud.data.len(),
+            3,
+            coordinate_type::<T>(),
+            values,
+        )?;
+    }
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+/// Reads a `.npz` bundle with a `points` array and optional `colors` and `normals`.
+///
+/// Both stored and compressed (`np.savez_compressed`) bundles are supported.
+pub fn read_npz<T>(filename: &str) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_npz_from_reader(BufReader::new(File::open(filename)?))
+}
+
+/// Same as `read_npz`, reading from any seekable source.
+pub fn read_npz_from_reader<T, R>(reader: R) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+    R: Read + Seek,
+{
+    let mut zip = zip::ZipArchive::new(reader)?;
+    let mut read_entry = |name: &str| -> Result<Option<NpyArray>> {
+        let array = match zip.by_name(&format!("{}.npy", name)) {
+            Ok(mut file) => read_array(&mut file)?,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if array.cols != 3 {
+            return Err(anyhow!(format!(
+                "Expected an Nx3 {:?} array, found {}x{}",
+                name, array.rows, array.cols
+            )));
+        }
+        Ok(Some(array))
+    };
+    let points = read_entry("points")?.ok_or_else(|| anyhow!("Missing points array in npz"))?;
+    let colors = read_entry("colors")?;
+    let normals = read_entry("normals")?;
+    for (name, array) in [("colors", &colors), ("normals", &normals)].iter() {
+        if let Some(array) = array {
+            if array.rows != points.rows {
+                return Err(anyhow!(format!(
+                    "npz holds {} points but {} {}",
+                    points.rows, array.rows, name
+                )));
+            }
+        }
+    }
+
+    let mut pointcloud = PointCloud::<T>::new();
+    for i in 0..points.rows {
+        let mut data = T::default();
+        let row = (i * 3)..(i * 3 + 3);
+        data.set_field_value("x", points.data[i * 3])?;
+        data.set_field_value("y", points.data[i * 3 + 1])?;
+        data.set_field_value("z", points.data[i * 3 + 2])?;
+        // Arrays the point type does not carry are ignored.
+        if let Some(colors) = &colors {
+            let _ = set_colors(&mut data, &colors.data[row.clone()]);
+        }
+        if let Some(normals) = &normals {
+            let _ = set_normals(&mut data, &normals.data[row]);
+        }
+        pointcloud.add_data(data);
+    }
+    Ok(pointcloud)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::{PointXYZ, PointXYZNormal, PointXYZRGBNormal};
+    use nalgebra::Vector3;
+    use std::io::Cursor;
+
+    fn colored_cloud() -> PointCloud<PointXYZRGBNormal<f64, u8, f64>> {
+        let mut cloud = PointCloud::new();
+        for i in 0..3 {
+            let v = i as f64;
+            cloud.add_data(PointXYZRGBNormal {
+                point: Vector3::new(v, -v, 0.5 * v),
+                color: Vector3::new(255, 51 * i as u8, 0),
+                normal: Vector3::new(0.0, 0.0, 1.0),
+            });
+        }
+        cloud
+    }
+
+    fn check_colored(read: &PointCloud<PointXYZRGBNormal<f64, u8, f64>>) {
+        let cloud = colored_cloud();
+        assert_eq!(read.len(), cloud.len());
+        for (a, b) in read.data.iter().zip(cloud.data.iter()) {
+            assert_eq!(a.point, b.point);
+            assert_eq!(a.color, b.color);
+            assert_eq!(a.normal, b.normal);
+        }
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let mut bytes = Vec::new();
+        write_npy_to_writer(&colored_cloud(), &mut bytes).unwrap();
+        let header = String::from_utf8_lossy(&bytes[10..128]).to_string();
+        assert!(header.contains("'descr': '<f8'"), "{}", header);
+        assert!(header.contains("'shape': (3, 9)"), "{}", header);
+        check_colored(&read_npy_from_reader(&bytes[..]).unwrap());
+
+        // An Nx6 array holds normals for point types without colors.
+        let normals = PointCloud::<PointXYZNormal<f32, f32>>::from_point_vec(vec![Vector3::new(
+            1.0, 2.0, 3.0,
+        )]);
+        let mut bytes = Vec::new();
+        write_npy_to_writer(&normals, &mut bytes).unwrap();
+        let read = read_npy_from_reader::<PointXYZNormal<f32, f32>, _>(&bytes[..]).unwrap();
+        assert_eq!(read.data[0].point, Vector3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn fortran_order_big_endian() {
+        let header = "{'descr': '>f4', 'fortran_order': True, 'shape': (2, 3), }";
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        // Columns are stored one after the other.
+        for v in [1.0f32, 4.0, 2.0, 5.0, 3.0, 6.0].iter() {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        let read = read_npy_from_reader::<PointXYZ<f32>, _>(&bytes[..]).unwrap();
+        assert_eq!(read.data[0].point, Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(read.data[1].point, Vector3::new(4.0, 5.0, 6.0));
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(read_npy_from_reader::<PointXYZ<f32>, _>(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn npz_round_trip() {
+        let mut bytes = Cursor::new(Vec::new());
+        write_npz_to_writer(&colored_cloud(), &mut bytes).unwrap();
+        bytes.set_position(0);
+        check_colored(&read_npz_from_reader(&mut bytes).unwrap());
+
+        // Missing arrays leave the fields at their default.
+        let mut bytes = Cursor::new(Vec::new());
+        let points = PointCloud::<PointXYZ<f64>>::from_point_vec(vec![Vector3::new(1.0, 2.0, 3.0)]);
+        write_npz_to_writer(&points, &mut bytes).unwrap();
+        bytes.set_position(0);
+        let read = read_npz_from_reader::<PointXYZRGBNormal<f64, u8, f64>, _>(&mut bytes).unwrap();
+        assert_eq!(read.data[0].point, Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(read.data[0].color, Vector3::zeros());
+    }
+}
@@ -0,0 +1,416 @@
+use crate::camera::{CameraModel, PinholeCameraIntrinsic};
+use crate::rgbdimage::{FloatImage, RGBDImage};
+use anyhow::{anyhow, Result};
+use image::Luma;
+use nalgebra::{
+    Isometry3, Matrix1x6, Matrix2x3, Matrix3x6, Matrix6, Point3, RowVector2, RowVector3,
+    Translation3, UnitQuaternion, Vector2, Vector3, Vector6,
+};
+
+/// Parameters of `compute_rgbd_odometry`.
+#[derive(Clone, Debug)]
+pub struct OdometryOption {
+    /// Gauss-Newton iterations of each pyramid level, from the coarsest one. Its length
+    /// is the number of levels.
+    pub iterations_per_level: Vec<usize>,
+    /// Largest depth difference in metres between corresponding pixels.
+    pub max_depth_diff: f64,
+    /// Source pixels whose depth is outside `[min_depth, max_depth]` are ignored.
+    pub min_depth: f64,
+    pub max_depth: f64,
+    /// Weight of the depth residuals against the intensity residuals, in [0, 1].
+    pub depth_weight: f64,
+}
+
+impl Default for OdometryOption {
+    fn default() -> Self {
+        OdometryOption {
+            iterations_per_level: vec![20, 10, 5],
+            max_depth_diff: 0.03,
+            min_depth: 0.0,
+            max_depth: 4.0,
+            depth_weight: 0.95,
+        }
+    }
+}
+
+const MIN_CORRESPONDENCES: usize = 10;
+
+/// Estimates the motion between two RGBD frames by minimizing intensity and depth
+/// differences (hybrid odometry) over an image pyramid, starting from `initial`.
+///
+/// Returns the transform mapping points of the `source` camera frame to the `target`
+/// camera frame, and the 6x6 information matrix of the final correspondences, ordered
+/// as rotation then translation. Images are expected to be undistorted: the
+/// distortion of `intrinsic` is ignored.
+pub fn compute_rgbd_odometry(
+    source: &RGBDImage,
+    target: &RGBDImage,
+    intrinsic: &PinholeCameraIntrinsic,
+    initial: &Isometry3<f64>,
+    option: &OdometryOption,
+) -> Result<(Isometry3<f64>, Matrix6<f64>)> {
+    for (name, rgbd) in [("source", source), ("target", target)].iter() {
+        if rgbd.color.dimensions() != rgbd.depth.dimensions() {
+            return Err(anyhow!(format!(
+                "The {} color and depth images have different sizes",
+                name
+            )));
+        }
+    }
+    if source.depth.dimensions() != target.depth.dimensions() {
+        return Err(anyhow!("Source and target images have different sizes"));
+    }
+    if option.iterations_per_level.is_empty() {
+        return Err(anyhow!("At least one pyramid level is required"));
+    }
+    let n_levels = option.iterations_per_level.len();
+    let source_pyramid = build_pyramid(source, intrinsic, n_levels);
+    let target_pyramid = build_pyramid(target, intrinsic, n_levels);
+    let mut transform = *initial;
+    for (level, iterations) in option.iterations_per_level.iter().enumerate() {
+        let level = n_levels - 1 - level;
+        let source = &source_pyramid[level];
+        let target = &target_pyramid[level];
+        let gradients = Gradients::new(target, option.max_depth_diff);
+        for _ in 0..*iterations {
+            let correspondences = find_correspondences(source, target, &transform, option);
+            if correspondences.len() < MIN_CORRESPONDENCES {
+                return Err(anyhow!(format!(
+                    "Only {} correspondences at pyramid level {}",
+                    correspondences.len(),
+                    level
+                )));
+            }
+            let (h, b) = linearize(source, target, &gradients, &correspondences, option);
+            let delta = h
+                .cholesky()
+                .ok_or_else(|| anyhow!("Degenerate odometry system"))?
+                .solve(&-b);
+            transform = exp(&delta) * transform;
+            if delta.norm() < 1e-6 {
+                break;
+            }
+        }
+    }
+    let finest = (&source_pyramid[0], &target_pyramid[0]);
+    let correspondences = find_correspondences(finest.0, finest.1, &transform, option);
+    let mut information = Matrix6::zeros();
+    for c in correspondences.iter() {
+        let g = point_jacobian(&c.target_point.coords);
+        information += g.transpose() * g;
+    }
+    Ok((transform, information))
+}
+
+/// One pyramid level of a frame.
+struct Level {
+    intensity: FloatImage,
+    depth: FloatImage,
+    camera: PinholeCameraIntrinsic,
+}
+
+fn build_pyramid(
+    rgbd: &RGBDImage,
+    intrinsic: &PinholeCameraIntrinsic,
+    n_levels: usize,
+) -> Vec<Level> {
+    let mut pyramid = vec![Level {
+        intensity: rgbd.intensity(),
+        depth: rgbd.depth.clone(),
+        camera: PinholeCameraIntrinsic::new(
+            rgbd.depth.width(),
+            rgbd.depth.height(),
+            intrinsic.fx,
+            intrinsic.fy,
+            intrinsic.cx,
+            intrinsic.cy,
+        ),
+    }];
+    for _ in 1..n_levels {
+        let previous = &pyramid[pyramid.len() - 1];
+        let (width, height) = (previous.depth.width() / 2, previous.depth.height() / 2);
+        let c = &previous.camera;
+        // Pixel centers are kept aligned across levels.
+        let camera = PinholeCameraIntrinsic::new(
+            width,
+            height,
+            c.fx / 2.0,
+            c.fy / 2.0,
+            (c.cx + 0.5) / 2.0 - 0.5,
+            (c.cy + 0.5) / 2.0 - 0.5,
+        );
+        let intensity = FloatImage::from_fn(width, height, |x, y| {
+            let sum: f32 = block(x, y)
+                .map(|(u, v)| previous.intensity.get_pixel(u, v)[0])
+                .sum();
+            Luma([sum / 4.0])
+        });
+        let depth = FloatImage::from_fn(width, height, |x, y| {
+            let valid: Vec<f32> = block(x, y)
+                .map(|(u, v)| previous.depth.get_pixel(u, v)[0])
+                .filter(|d| *d > 0.0)
+                .collect();
+            if valid.is_empty() {
+                Luma([0.0])
+            } else {
+                Luma([valid.iter().sum::<f32>() / valid.len() as f32])
+            }
+        });
+        pyramid.push(Level {
+            intensity,
+            depth,
+            camera,
+        });
+    }
+    pyramid
+}
+
+/// Pixels of the previous level covered by pixel `(x, y)`.
+fn block(x: u32, y: u32) -> impl Iterator<Item = (u32, u32)> {
+    [(0, 0), (1, 0), (0, 1), (1, 1)]
+        .iter()
+        .map(move |(dx, dy)| (2 * x + dx, 2 * y + dy))
+}
+
+/// Sobel gradients of the target intensity and depth, in units per pixel.
+struct Gradients {
+    intensity: Vec<RowVector2<f64>>,
+    depth: Vec<Option<RowVector2<f64>>>,
+    width: u32,
+}
+
+impl Gradients {
+    fn new(level: &Level, max_depth_diff: f64) -> Gradients {
+        let (width, height) = level.depth.dimensions();
+        let mut intensity = vec![RowVector2::zeros(); (width * height) as usize];
+        let mut depth = vec![None; (width * height) as usize];
+        for y in 1..height.saturating_sub(1) {
+            for x in 1..width.saturating_sub(1) {
+                let i = (y * width + x) as usize;
+                let at = |image: &FloatImage, dx: i32, dy: i32| {
+                    image.get_pixel((x as i32 + dx) as u32, (y as i32 + dy) as u32)[0] as f64
+                };
+                let sobel = |image: &FloatImage| {
+                    let gx = (at(image, 1, -1) + 2.0 * at(image, 1, 0) + at(image, 1, 1)
+                        - at(image, -1, -1)
+                        - 2.0 * at(image, -1, 0)
+                        - at(image, -1, 1))
+                        / 8.0;
+                    let gy = (at(image, -1, 1) + 2.0 * at(image, 0, 1) + at(image, 1, 1)
+                        - at(image, -1, -1)
+                        - 2.0 * at(image, 0, -1)
+                        - at(image, 1, -1))
+                        / 8.0;
+                    RowVector2::new(gx, gy)
+                };
+                intensity[i] = sobel(&level.intensity);
+                // Depth gradients are only kept on smooth surfaces, whose second
+                // differences are small even when they are slanted.
+                let center = at(&level.depth, 0, 0);
+                let valid = (-1..=1).all(|dy| (-1..=1).all(|dx| at(&level.depth, dx, dy) > 0.0));
+                let smooth = valid
+                    && [(1, 0), (0, 1), (1, 1), (1, -1)].iter().all(|(dx, dy)| {
+                        (at(&level.depth, *dx, *dy) + at(&level.depth, -dx, -dy) - 2.0 * center)
+                            .abs()
+                            <= max_depth_diff
+                    });
+                if smooth {
+                    depth[i] = Some(sobel(&level.depth));
+                }
+            }
+        }
+        Gradients {
+            intensity,
+            depth,
+            width,
+        }
+    }
+}
+
+struct Correspondence {
+    source_pixel: (u32, u32),
+    /// Source point moved to the target camera frame.
+    target_point: Point3<f64>,
+    target_pixel: (u32, u32),
+}
+
+/// Pairs each valid source pixel with the target pixel its point projects to, when
+/// their depths agree.
+fn find_correspondences(
+    source: &Level,
+    target: &Level,
+    transform: &Isometry3<f64>,
+    option: &OdometryOption,
+) -> Vec<Correspondence> {
+    let (width, height) = target.depth.dimensions();
+    let mut correspondences = Vec::new();
+    for (x, y, pixel) in source.depth.enumerate_pixels() {
+        let d = pixel[0] as f64;
+        if d <= 0.0 || d < option.min_depth || d > option.max_depth {
+            continue;
+        }
+        let source_point = source
+            .camera
+            .back_project(&Vector2::new(x as f64, y as f64), d);
+        let p = transform * Point3::from(source_point);
+        let uv = match target.camera.project(&p.coords) {
+            Some(uv) => uv,
+            None => continue,
+        };
+        let (u, v) = (uv[0].round(), uv[1].round());
+        if u < 0.0 || v < 0.0 || u >= width as f64 || v >= height as f64 {
+            continue;
+        }
+        let (u, v) = (u as u32, v as u32);
+        let target_depth = target.depth.get_pixel(u, v)[0] as f64;
+        if target_depth <= 0.0 || (target_depth - p[2]).abs() > option.max_depth_diff {
+            continue;
+        }
+        correspondences.push(Correspondence {
+            source_pixel: (x, y),
+            target_point: p,
+            target_pixel: (u, v),
+        });
+    }
+    correspondences
+}
+
+/// Gauss-Newton normal equations of the weighted intensity and depth residuals.
+fn linearize(
+    source: &Level,
+    target: &Level,
+    gradients: &Gradients,
+    correspondences: &[Correspondence],
+    option: &OdometryOption,
+) -> (Matrix6<f64>, Vector6<f64>) {
+    let sqrt_depth_weight = option.depth_weight.sqrt();
+    let sqrt_intensity_weight = (1.0 - option.depth_weight).sqrt();
+    let camera = &target.camera;
+    let mut h = Matrix6::zeros();
+    let mut b = Vector6::zeros();
+    for c in correspondences.iter() {
+        let (u, v) = c.target_pixel;
+        let i = (v * gradients.width + u) as usize;
+        let p = c.target_point.coords;
+        let projection = Matrix2x3::new(
+            camera.fx / p[2],
+            0.0,
+            -camera.fx * p[0] / (p[2] * p[2]),
+            0.0,
+            camera.fy / p[2],
+            -camera.fy * p[1] / (p[2] * p[2]),
+        );
+        let g = point_jacobian(&p);
+        let mut add = |jacobian: Matrix1x6<f64>, residual: f64, weight: f64| {
+            let j = jacobian * weight;
+            h += j.transpose() * j;
+            b += j.transpose() * (residual * weight);
+        };
+        let (x, y) = c.source_pixel;
+        add(
+            gradients.intensity[i] * projection * g,
+            target.intensity.get_pixel(u, v)[0] as f64 - source.intensity.get_pixel(x, y)[0] as f64,
+            sqrt_intensity_weight,
+        );
+        if let Some(depth_gradient) = gradients.depth[i] {
+            add(
+                (depth_gradient * projection - RowVector3::new(0.0, 0.0, 1.0)) * g,
+                target.depth.get_pixel(u, v)[0] as f64 - p[2],
+                sqrt_depth_weight,
+            );
+        }
+    }
+    (h, b)
+}
+
+/// Derivative of `exp(delta) * p` with respect to `delta`, rotation first.
+fn point_jacobian(p: &Vector3<f64>) -> Matrix3x6<f64> {
+    Matrix3x6::new(
+        0.0, p[2], -p[1], 1.0, 0.0, 0.0, //
+        -p[2], 0.0, p[0], 0.0, 1.0, 0.0, //
+        p[1], -p[0], 0.0, 0.0, 0.0, 1.0,
+    )
+}
+
+fn exp(delta: &Vector6<f64>) -> Isometry3<f64> {
+    Isometry3::from_parts(
+        Translation3::new(delta[3], delta[4], delta[5]),
+        UnitQuaternion::from_scaled_axis(Vector3::new(delta[0], delta[1], delta[2])),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::{PointCloud, PointColor, PointXYZRGB};
+
+    /// Textured wavy wall with a floor, seen from the origin along +z.
+    fn scene() -> PointCloud<PointXYZRGB<f64, f32>> {
+        let mut pointcloud = PointCloud::new();
+        let texture = |a: f64, b: f64| {
+            let i = (0.5 + 0.25 * (7.0 * a).sin() + 0.25 * (5.0 * b).cos()) as f32;
+            Vector3::new(i, i, i)
+        };
+        let step = 0.01;
+        for i in 0..440 {
+            let x = -2.2 + i as f64 * step;
+            for j in 0..270 {
+                let y = -1.7 + j as f64 * step;
+                let z = 3.0 + 0.1 * (3.0 * x).sin() * (2.0 * y).cos();
+                pointcloud.add_data(PointXYZRGB::from_point_color(
+                    Vector3::new(x, y, z),
+                    texture(x, y),
+                ));
+            }
+            for k in 0..200 {
+                let z = 1.0 + k as f64 * step;
+                pointcloud.add_data(PointXYZRGB::from_point_color(
+                    Vector3::new(x, 1.0, z),
+                    texture(x, z),
+                ));
+            }
+        }
+        pointcloud
+    }
+
+    #[test]
+    fn recovers_small_rigid_motion() {
+        let intrinsic = PinholeCameraIntrinsic::new(320, 240, 260.0, 260.0, 159.5, 119.5);
+        let pointcloud = scene();
+        let source_pose = Isometry3::identity();
+        let target_pose = Isometry3::from_parts(
+            Translation3::new(0.05, -0.03, 0.04),
+            UnitQuaternion::from_euler_angles(0.02, -0.03, 0.015),
+        );
+        let render = |pose: &Isometry3<f64>| {
+            RGBDImage::from_pointcloud(&pointcloud, &intrinsic, pose.to_homogeneous(), 1, 2)
+                .unwrap()
+        };
+        let (source, target) = (render(&source_pose), render(&target_pose));
+        let (transform, information) = compute_rgbd_odometry(
+            &source,
+            &target,
+            &intrinsic,
+            &Isometry3::identity(),
+            &OdometryOption {
+                iterations_per_level: vec![10, 5, 3],
+                ..OdometryOption::default()
+            },
+        )
+        .unwrap();
+        let expected = target_pose.inverse() * source_pose;
+        let error = expected.inverse() * transform;
+        assert!(
+            error.translation.vector.norm() < 0.01,
+            "translation error {}",
+            error.translation.vector.norm()
+        );
+        assert!(
+            error.rotation.angle() < 0.005,
+            "rotation error {}",
+            error.rotation.angle()
+        );
+        assert!(information[(5, 5)] > 0.0);
+    }
+}
@@ -1,165 +1,1308 @@
-use super::pointcloud::{FloatData, Point, PointCloud};
-use anyhow::*;
+use super::dynamic_pointcloud::DynamicPointCloud;
+use super::pointcloud::{is_packed_color, FieldType, Point, PointCloud, PointField};
+use anyhow::{anyhow, Result};
 use core::convert::TryInto;
 use csv::{ReaderBuilder, StringRecord};
 use itertools_num::*;
-use num_traits::{Float, NumAssign};
-use serde::Deserialize;
-use std::fmt::Debug;
+use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion};
+use num_traits::NumAssign;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::str::FromStr;
 
-#[derive(Default, Debug)]
-struct PCDHeader {
-    version: String,
-    fields: Vec<String>,
-    size: Vec<usize>,
-    field_type: Vec<String>,
-    count: Vec<usize>,
-    width: usize,
-    height: usize,
-    data: String,
+/// Encoding of the data section of a PCD file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PCDEncoding {
+    Ascii,
+    Binary,
+    BinaryCompressed,
 }
 
-fn get_data_from_record<T>(record: &StringRecord, header: &PCDHeader) -> Result<T>
+impl PCDEncoding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PCDEncoding::Ascii => "ascii",
+            PCDEncoding::Binary => "binary",
+            PCDEncoding::BinaryCompressed => "binary_compressed",
+        }
+    }
+}
+
+impl FromStr for PCDEncoding {
+    type Err = PcdError;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(PCDEncoding::Ascii),
+            "binary" => Ok(PCDEncoding::Binary),
+            "binary_compressed" => Ok(PCDEncoding::BinaryCompressed),
+            &_ => Err(PcdError::InvalidHeaderValue {
+                entry: "DATA",
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Errors raised while decoding a malformed PCD file.
+///
+/// They are returned inside `anyhow::Error` and can be recovered with `downcast_ref`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PcdError {
+    /// A required header entry is absent.
+    MissingField(&'static str),
+    /// A header entry has a value that cannot be parsed.
+    InvalidHeaderValue {
+        entry: &'static str,
+        value: String,
+    },
+    /// SIZE, TYPE or COUNT does not list one value per entry of FIELDS.
+    FieldCountMismatch {
+        entry: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    UnsupportedVersion(String),
+    /// The data section is shorter than the header announces, in bytes for binary
+    /// data and in records for ascii data.
+    TruncatedData {
+        expected: usize,
+        found: usize,
+    },
+    /// A value of the data section cannot be parsed.
+    InvalidData(String),
+    DecompressionFailed(String),
+    /// POINTS differs from WIDTH * HEIGHT.
+    PointCountMismatch {
+        points: usize,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl fmt::Display for PcdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PcdError::MissingField(entry) => write!(f, "missing {} entry in PCD header", entry),
+            PcdError::InvalidHeaderValue { entry, value } => {
+                write!(f, "invalid value {:?} for {} in PCD header", value, entry)
+            }
+            PcdError::FieldCountMismatch {
+                entry,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} has {} values but FIELDS has {} in PCD header",
+                entry, found, expected
+            ),
+            PcdError::UnsupportedVersion(version) => {
+                write!(f, "unsupported PCD version {:?}", version)
+            }
+            PcdError::TruncatedData { expected, found } => write!(
+                f,
+                "truncated PCD data: expected {}, found {}",
+                expected, found
+            ),
+            PcdError::InvalidData(msg) => write!(f, "invalid PCD data: {}", msg),
+            PcdError::DecompressionFailed(msg) => {
+                write!(f, "failed to decompress PCD data: {}", msg)
+            }
+            PcdError::PointCountMismatch {
+                points,
+                width,
+                height,
+            } => write!(
+                f,
+                "POINTS {} does not match WIDTH {} x HEIGHT {}",
+                points, width, height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PcdError {}
+
+/// Header of a PCD file.
+#[derive(Clone, Debug)]
+pub struct PCDHeader {
+    pub version: String,
+    pub fields: Vec<String>,
+    pub size: Vec<usize>,
+    pub field_type: Vec<String>,
+    pub count: Vec<usize>,
+    pub width: usize,
+    pub height: usize,
+    /// Sensor acquisition pose, parsed from `VIEWPOINT tx ty tz qw qx qy qz`.
+    pub viewpoint: Isometry3<f64>,
+    pub points: usize,
+    pub data: PCDEncoding,
+}
+
+impl PCDHeader {
+    /// Typed description of each field, or `None` for TYPE/SIZE pairs that are not supported.
+    pub fn field_types(&self) -> Vec<Option<FieldType>> {
+        self.field_type
+            .iter()
+            .zip(self.size.iter())
+            .map(|(t, s)| FieldType::from_pcd(t, *s))
+            .collect()
+    }
+}
+
+fn parse_header_value<V: FromStr>(entry: &'static str, value: &str) -> Result<V, PcdError> {
+    value.parse().map_err(|_| PcdError::InvalidHeaderValue {
+        entry,
+        value: value.to_string(),
+    })
+}
+
+fn parse_header_values<V: FromStr>(
+    entry: &'static str,
+    values: &[&str],
+) -> Result<Vec<V>, PcdError> {
+    values
+        .iter()
+        .map(|v| parse_header_value(entry, v))
+        .collect()
+}
+
+fn parse_single_value<V: FromStr>(entry: &'static str, values: &[&str]) -> Result<V, PcdError> {
+    match values {
+        [value] => parse_header_value(entry, value),
+        _ => Err(PcdError::InvalidHeaderValue {
+            entry,
+            value: values.join(" "),
+        }),
+    }
+}
+
+fn check_field_count(entry: &'static str, expected: usize, found: usize) -> Result<(), PcdError> {
+    if expected != found {
+        return Err(PcdError::FieldCountMismatch {
+            entry,
+            expected,
+            found,
+        });
+    }
+    Ok(())
+}
+
+fn parse_viewpoint(values: &[&str]) -> Result<Isometry3<f64>, PcdError> {
+    let v: Vec<f64> = parse_header_values("VIEWPOINT", values)?;
+    if v.len() != 7 || v[3..].iter().all(|q| *q == 0.0) {
+        return Err(PcdError::InvalidHeaderValue {
+            entry: "VIEWPOINT",
+            value: values.join(" "),
+        });
+    }
+    Ok(Isometry3::from_parts(
+        Translation3::new(v[0], v[1], v[2]),
+        UnitQuaternion::from_quaternion(Quaternion::new(v[3], v[4], v[5], v[6])),
+    ))
+}
+
+/// Reads header lines up to and including the DATA line.
+fn parse_header<R: BufRead>(reader: &mut R) -> Result<PCDHeader> {
+    let mut version = None;
+    let mut fields = None;
+    let mut size = None;
+    let mut field_type = None;
+    let mut count = None;
+    let mut width = None;
+    let mut height = None;
+    let mut viewpoint = None;
+    let mut points = None;
+    let mut data = None;
+
+    let mut buf = String::new();
+    while data.is_none() {
+        buf.clear();
+        if reader.read_line(&mut buf)? == 0 {
+            break;
+        }
+        let v: Vec<&str> = buf.split_whitespace().collect();
+        let (key, values) = match v.split_first() {
+            Some((key, values)) if !key.starts_with('#') => (*key, values),
+            _ => continue,
+        };
+        match key {
+            "VERSION" => version = Some(parse_single_value::<String>("VERSION", values)?),
+            "FIELDS" => fields = Some(values.iter().map(|s| s.to_string()).collect::<Vec<_>>()),
+            "SIZE" => size = Some(parse_header_values("SIZE", values)?),
+            "TYPE" => field_type = Some(values.iter().map(|s| s.to_string()).collect()),
+            "COUNT" => count = Some(parse_header_values("COUNT", values)?),
+            "WIDTH" => width = Some(parse_single_value("WIDTH", values)?),
+            "HEIGHT" => height = Some(parse_single_value("HEIGHT", values)?),
+            "VIEWPOINT" => viewpoint = Some(parse_viewpoint(values)?),
+            "POINTS" => points = Some(parse_single_value("POINTS", values)?),
+            "DATA" => data = Some(parse_single_value("DATA", values)?),
+            &_ => (),
+        }
+    }
+
+    let version = version.unwrap_or_else(|| "0.7".to_string());
+    if !matches!(version.as_str(), "0.5" | ".5" | "0.6" | ".6" | "0.7" | ".7") {
+        return Err(PcdError::UnsupportedVersion(version).into());
+    }
+    let fields = fields
+        .filter(|f| !f.is_empty())
+        .ok_or(PcdError::MissingField("FIELDS"))?;
+    let size: Vec<usize> = size.ok_or(PcdError::MissingField("SIZE"))?;
+    let field_type: Vec<String> = field_type.ok_or(PcdError::MissingField("TYPE"))?;
+    let count: Vec<usize> = count.unwrap_or_else(|| vec![1; fields.len()]);
+    check_field_count("SIZE", fields.len(), size.len())?;
+    check_field_count("TYPE", fields.len(), field_type.len())?;
+    check_field_count("COUNT", fields.len(), count.len())?;
+    let width: usize = width.ok_or(PcdError::MissingField("WIDTH"))?;
+    let height: usize = height.unwrap_or(1);
+    let n_points = width
+        .checked_mul(height)
+        .ok_or_else(|| PcdError::InvalidHeaderValue {
+            entry: "WIDTH",
+            value: width.to_string(),
+        })?;
+    let points = points.unwrap_or(n_points);
+    if points != n_points {
+        return Err(PcdError::PointCountMismatch {
+            points,
+            width,
+            height,
+        }
+        .into());
+    }
+    let data = data.ok_or(PcdError::MissingField("DATA"))?;
+    let header = PCDHeader {
+        version,
+        fields,
+        size,
+        field_type,
+        count,
+        width,
+        height,
+        viewpoint: viewpoint.unwrap_or_else(Isometry3::identity),
+        points,
+        data,
+    };
+    // Sizes and columns derived from the header must not overflow.
+    field_columns(&header)?;
+    data_size(&header)?;
+    Ok(header)
+}
+
+fn decode_binary_value(bytes: &[u8], datatype: FieldType) -> Result<f64> {
+    let value = match datatype {
+        FieldType::I8 => i8::from_le_bytes(bytes.try_into()?) as f64,
+        FieldType::U8 => u8::from_le_bytes(bytes.try_into()?) as f64,
+        FieldType::I16 => i16::from_le_bytes(bytes.try_into()?) as f64,
+        FieldType::U16 => u16::from_le_bytes(bytes.try_into()?) as f64,
+        FieldType::I32 => i32::from_le_bytes(bytes.try_into()?) as f64,
+        FieldType::U32 => u32::from_le_bytes(bytes.try_into()?) as f64,
+        FieldType::I64 => i64::from_le_bytes(bytes.try_into()?) as f64,
+        FieldType::U64 => u64::from_le_bytes(bytes.try_into()?) as f64,
+        FieldType::F32 => f32::from_le_bytes(bytes.try_into()?) as f64,
+        FieldType::F64 => f64::from_le_bytes(bytes.try_into()?),
+    };
+    Ok(value)
+}
+
+/// Parses an ascii packed color, written by PCL either as an integer or as the float
+/// sharing its bit pattern.
+fn parse_packed_color(text: &str) -> Result<f64> {
+    if let Ok(packed) = text.parse::<u32>() {
+        return Ok(packed as f64);
+    }
+    let value = text
+        .parse::<f32>()
+        .map_err(|_| PcdError::InvalidData(format!("invalid color {:?}", text)))?;
+    Ok(value.to_bits() as f64)
+}
+
+fn set_point_field<T: Point>(data: &mut T, name: &str, values: &mut [f64], keep_nan: bool) {
+    if !keep_nan {
+        for v in values.iter_mut().filter(|v| v.is_nan()) {
+            *v = 0.0;
+        }
+    }
+    // Fields the point type does not carry are ignored.
+    let _ = data.set_field_values(name, values);
+}
+
+fn get_data_from_record<T>(
+    record: &StringRecord,
+    header: &PCDHeader,
+    field_types: &[Option<FieldType>],
+    field_columns: &[usize],
+    keep_nan: bool,
+) -> Result<T>
 where
     T: Point + Default,
-    <T as Point>::Item: FloatData + FromStr,
-    <<T as Point>::Item as FromStr>::Err: Send + Debug + Into<Error>,
 {
     let mut data = T::default();
-    for (i, (field, field_type)) in header
+    let mut values = Vec::new();
+    for (((field, field_type), count), first_column) in header
         .fields
         .iter()
-        .zip(header.field_type.iter())
-        .enumerate()
+        .zip(field_types.iter())
+        .zip(header.count.iter())
+        .zip(field_columns.iter())
     {
-        if field_type != "F" {
+        if field_type.is_none() {
             continue;
         }
-        let field = data.point_field_mut(field.as_str());
-        if let Ok(field_ok) = field {
-            let res = record[i]
-                .parse::<<T as Point>::Item>()
-                .map_err(|e| anyhow!(e))?;
-            if !res.is_nan() {
-                *field_ok = res;
-            }
+        values.clear();
+        for i in *first_column..first_column + count {
+            let text = record.get(i).ok_or_else(|| {
+                PcdError::InvalidData(format!("missing value for field {:?}", field))
+            })?;
+            values.push(if is_packed_color(field) {
+                parse_packed_color(text)?
+            } else {
+                text.parse::<f64>()
+                    .map_err(|_| PcdError::InvalidData(format!("invalid value {:?}", text)))?
+            });
         }
+        set_point_field(&mut data, field, &mut values, keep_nan);
     }
     Ok(data)
 }
 
-fn get_data_from_binary<T>(buf_chunk: &[u8], header: &PCDHeader) -> Result<T>
+fn get_data_from_binary<T>(
+    buf_chunk: &[u8],
+    header: &PCDHeader,
+    field_types: &[Option<FieldType>],
+    field_offsets: &[usize],
+    keep_nan: bool,
+) -> Result<T>
 where
     T: Point + Default,
-    <T as Point>::Item: FloatData + NumAssign + Copy + for<'de> Deserialize<'de>,
 {
     let mut data = T::default();
-    let mut i_start = 0;
-    let item_size = std::mem::size_of::<<T as Point>::Item>();
-    for (field, field_type) in header.fields.iter().zip(header.field_type.iter()) {
-        if field_type != "F" {
-            continue;
-        }
-        let field = data.point_field_mut(field.as_str());
-        if let Ok(field_ok) = field {
-            let res: <T as Point>::Item = bincode::deserialize(&buf_chunk[i_start..(i_start + item_size)])?;
-            if !res.is_nan() {
-                *field_ok = res;
+    let mut values = Vec::new();
+    for (i, ((field, field_type), count)) in header
+        .fields
+        .iter()
+        .zip(field_types.iter())
+        .zip(header.count.iter())
+        .enumerate()
+    {
+        // Unknown types are skipped by their declared byte width through the offsets.
+        if let Some(datatype) = field_type {
+            let size = datatype.size();
+            let i_start = field_offsets[i];
+            let bytes = buf_chunk.get(i_start..(i_start + size * count)).ok_or(
+                PcdError::TruncatedData {
+                    expected: i_start + size * count,
+                    found: buf_chunk.len(),
+                },
+            )?;
+            values.clear();
+            for item in bytes.chunks(size) {
+                values.push(if is_packed_color(field) && size == 4 {
+                    u32::from_le_bytes(item.try_into()?) as f64
+                } else {
+                    decode_binary_value(item, *datatype)?
+                });
             }
+            set_point_field(&mut data, field, &mut values, keep_nan);
         }
-        i_start += item_size;
     }
     Ok(data)
 }
 
+/// Reads the header of a PCD file without decoding its point data.
+pub fn read_pcd_header(filename: &str) -> Result<PCDHeader> {
+    parse_header(&mut BufReader::new(File::open(filename)?))
+}
+
+/// Same as `read_pcd_header`, reading from any buffered source.
+///
+/// The reader is left positioned at the start of the data section.
+pub fn read_pcd_header_from_reader<R: BufRead>(reader: &mut R) -> Result<PCDHeader> {
+    parse_header(reader)
+}
+
+/// Reads a PCD file.
+///
+/// Every point is kept, with NaN values replaced by 0, and organized clouds keep their
+/// WIDTH x HEIGHT layout. Use `read_pcd_organized` to keep NaN values, and
+/// `remove_non_finite_points` to drop invalid points.
 pub fn read_pcd<T>(filename: &str) -> Result<PointCloud<T>>
 where
-    T: Point + Default + Debug,
-    <T as Point>::Item: NumAssign + Copy + FromStr + for<'de> Deserialize<'de>,
-    <<T as Point>::Item as FromStr>::Err: Send + Debug + Into<Error>,
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
 {
-    let mut pointcloud = PointCloud::<T>::new();
-    let mut reader = BufReader::new(File::open(filename)?);
-    let mut buf = String::new();
+    read_pcd_with_layout(BufReader::new(File::open(filename)?), false)
+}
 
-    let mut header = PCDHeader::default();
-    while reader.read_line(&mut buf)? > 0 {
-        buf.retain(|c| c != '\n');
-        let is_header_end = {
-            let v: Vec<&str> = buf.split(' ').collect();
-            match v[0] {
-                "VERSION" => header.version = v[1].to_string(),
-                "FIELDS" => header.fields = v[1..].iter().map(|s| s.to_string()).collect(),
-                "SIZE" => header.size = v[1..].iter().map(|s| s.parse().unwrap()).collect(),
-                "TYPE" => header.field_type = v[1..].iter().map(|s| s.to_string()).collect(),
-                "COUNT" => header.count = v[1..].iter().map(|s| s.parse().unwrap()).collect(),
-                "WIDTH" => header.width = v[1].parse().unwrap(),
-                "HEIGHT" => header.height = v[1].parse().unwrap(),
-                "DATA" => header.data = v[1].to_string(),
-                &_ => (),
-            }
-            v[0] == "DATA"
-        };
-        buf.clear();
-        if is_header_end {
-            break;
-        }
+/// Reads a PCD file keeping NaN values, so that invalid points of organized clouds
+/// remain as NaN placeholders in their WIDTH x HEIGHT layout.
+pub fn read_pcd_organized<T>(filename: &str) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_pcd_with_layout(BufReader::new(File::open(filename)?), true)
+}
+
+/// Same as `read_pcd`, reading the PCD content from any buffered source.
+pub fn read_pcd_from_reader<T, R>(reader: R) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+    R: BufRead,
+{
+    read_pcd_with_layout(reader, false)
+}
+
+/// Same as `read_pcd_organized`, reading the PCD content from any buffered source.
+pub fn read_pcd_organized_from_reader<T, R>(reader: R) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+    R: BufRead,
+{
+    read_pcd_with_layout(reader, true)
+}
+
+/// Same as `read_pcd`, reading the PCD content from memory.
+pub fn read_pcd_from_bytes<T>(bytes: &[u8]) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_pcd_with_layout(bytes, false)
+}
+
+/// Same as `read_pcd_organized`, reading the PCD content from memory.
+pub fn read_pcd_organized_from_bytes<T>(bytes: &[u8]) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_pcd_with_layout(bytes, true)
+}
+
+/// Data section of a PCD file.
+enum PCDBody {
+    Ascii(Vec<StringRecord>),
+    /// Interleaved binary points, `point_size` bytes each.
+    Binary {
+        data: Vec<u8>,
+        point_size: usize,
+    },
+}
+
+/// Byte size of each field, COUNT x SIZE.
+fn field_sizes(header: &PCDHeader) -> Result<Vec<usize>, PcdError> {
+    header
+        .count
+        .iter()
+        .zip(header.size.iter())
+        .map(|(c, s)| {
+            c.checked_mul(*s)
+                .ok_or_else(|| PcdError::InvalidHeaderValue {
+                    entry: "COUNT",
+                    value: c.to_string(),
+                })
+        })
+        .collect()
+}
+
+/// Byte offset of each field in an interleaved binary point, followed by the point size.
+fn field_offsets(header: &PCDHeader) -> Result<Vec<usize>, PcdError> {
+    let mut offsets = vec![0];
+    let mut offset: usize = 0;
+    for (field_size, size) in field_sizes(header)?.iter().zip(header.size.iter()) {
+        offset = offset
+            .checked_add(*field_size)
+            .ok_or_else(|| PcdError::InvalidHeaderValue {
+                entry: "SIZE",
+                value: size.to_string(),
+            })?;
+        offsets.push(offset);
     }
+    Ok(offsets)
+}
+
+/// Ascii column of the first value of each field.
+fn field_columns(header: &PCDHeader) -> Result<Vec<usize>, PcdError> {
+    let mut columns = Vec::with_capacity(header.count.len());
+    let mut column: usize = 0;
+    for count in header.count.iter() {
+        columns.push(column);
+        column = column
+            .checked_add(*count)
+            .ok_or_else(|| PcdError::InvalidHeaderValue {
+                entry: "COUNT",
+                value: count.to_string(),
+            })?;
+    }
+    Ok(columns)
+}
+
+/// Size in bytes of the data section of a binary PCD.
+fn data_size(header: &PCDHeader) -> Result<usize, PcdError> {
+    let point_size = *field_offsets(header)?.last().unwrap_or(&0);
+    header
+        .points
+        .checked_mul(point_size)
+        .ok_or_else(|| PcdError::InvalidHeaderValue {
+            entry: "POINTS",
+            value: header.points.to_string(),
+        })
+}
+
+/// Reads the header and the data section, decompressing it if needed.
+fn read_pcd_body<R: BufRead>(mut reader: R) -> Result<(PCDHeader, PCDBody)> {
+    let header = parse_header(&mut reader)?;
 
     let mut buf = Vec::new();
     reader.read_to_end(&mut buf)?;
-    let field_sizes = header
-        .count
+    let field_sizes = field_sizes(&header)?;
+    let fsize = *field_offsets(&header)?.last().unwrap_or(&0);
+    let data_size = data_size(&header)?;
+
+    let body = match header.data {
+        PCDEncoding::Ascii => {
+            let buf_str = String::from_utf8_lossy(&buf);
+            let mut reader = ReaderBuilder::new()
+                .delimiter(b' ')
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(buf_str.as_bytes());
+            let records = reader
+                .records()
+                .take(header.points)
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| PcdError::InvalidData(e.to_string()))?;
+            if records.len() < header.points {
+                return Err(PcdError::TruncatedData {
+                    expected: header.points,
+                    found: records.len(),
+                }
+                .into());
+            }
+            PCDBody::Ascii(records)
+        }
+        PCDEncoding::Binary | PCDEncoding::BinaryCompressed => {
+            if header.data == PCDEncoding::BinaryCompressed {
+                buf = decompress_columns(&buf, &header, &field_sizes, data_size)?;
+            }
+            if buf.len() < data_size {
+                return Err(PcdError::TruncatedData {
+                    expected: data_size,
+                    found: buf.len(),
+                }
+                .into());
+            }
+            buf.truncate(data_size);
+            PCDBody::Binary {
+                data: buf,
+                point_size: fsize,
+            }
+        }
+    };
+    Ok((header, body))
+}
+
+fn read_pcd_with_layout<T, R>(reader: R, keep_nan: bool) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+    R: BufRead,
+{
+    let mut pointcloud = PointCloud::<T>::new();
+    let (header, body) = read_pcd_body(reader)?;
+    let field_types = header.field_types();
+
+    match body {
+        PCDBody::Ascii(records) => {
+            let field_columns = field_columns(&header)?;
+            for record in records.iter() {
+                pointcloud.add_data(get_data_from_record(
+                    record,
+                    &header,
+                    &field_types,
+                    &field_columns,
+                    keep_nan,
+                )?);
+            }
+        }
+        PCDBody::Binary { data, point_size } => {
+            let field_offsets = field_offsets(&header)?;
+            if point_size > 0 {
+                for buf_chunk in data.chunks(point_size) {
+                    pointcloud.add_data(get_data_from_binary(
+                        buf_chunk,
+                        &header,
+                        &field_types,
+                        &field_offsets,
+                        keep_nan,
+                    )?);
+                }
+            }
+        }
+    }
+    if pointcloud.len() == header.points {
+        pointcloud.width = header.width as u32;
+        pointcloud.height = header.height as u32;
+    }
+    Ok(pointcloud)
+}
+
+/// Reads every field of a PCD file into a `DynamicPointCloud`.
+///
+/// Values keep their declared type, invalid points are kept and organized clouds
+/// keep their WIDTH x HEIGHT layout. Fields whose TYPE and SIZE pair is not
+/// supported are skipped.
+pub fn read_pcd_dynamic(filename: &str) -> Result<DynamicPointCloud> {
+    read_pcd_dynamic_from_reader(BufReader::new(File::open(filename)?))
+}
+
+/// Same as `read_pcd_dynamic`, reading the PCD content from any buffered source.
+pub fn read_pcd_dynamic_from_reader<R: BufRead>(reader: R) -> Result<DynamicPointCloud> {
+    let (header, body) = read_pcd_body(reader)?;
+    let field_types = header.field_types();
+    let known = header
+        .fields
         .iter()
-        .zip(header.size.iter())
-        .map(|(c, s)| c * s)
+        .zip(field_types.iter())
+        .zip(header.count.iter())
+        .enumerate()
+        .filter_map(|(i, ((name, datatype), count))| {
+            datatype.map(|datatype| {
+                (
+                    i,
+                    PointField {
+                        name: name.clone(),
+                        datatype,
+                        count: *count,
+                    },
+                )
+            })
+        })
         .collect::<Vec<_>>();
-    let field_offsets = [vec![0], field_sizes.iter().cumsum().collect::<Vec<usize>>()].concat();
-    let fsize: usize = field_sizes.iter().sum();
-
-    if header.data == "binary_compressed" {
-        let uncompressed_size = u32::from_be_bytes(buf[4..8].try_into()?) as usize;
-        let res_decomp =
-            lzf::decompress(&buf[8..buf.len()], uncompressed_size).map_err(Error::msg)?;
-        let offsets = [
-            vec![0],
-            field_sizes
-                .iter()
-                .map(|fs| fs * header.width * header.height)
-                .cumsum()
-                .collect::<Vec<usize>>(),
-        ]
-        .concat();
+    let mut cloud = DynamicPointCloud::new(known.iter().map(|(_, f)| f.clone()).collect());
 
-        buf.resize(uncompressed_size, 0);
-        for i in 1..(header.width * header.height) {
-            for (j, fs) in field_sizes.iter().enumerate() {
-                let i_start = i * fsize + field_offsets[j];
-                let i_end = i_start + fs;
-                buf[i_start..i_end].copy_from_slice(
-                    &res_decomp[(offsets[j] + i * fs)..(offsets[j] + (i + 1) * fs)],
-                );
+    match body {
+        PCDBody::Ascii(records) => {
+            let columns = field_columns(&header)?;
+            for record in records.iter() {
+                for (j, (i, field)) in known.iter().enumerate() {
+                    for k in 0..field.count {
+                        let text = record.get(columns[*i] + k).ok_or_else(|| {
+                            PcdError::InvalidData(format!(
+                                "missing value for field {:?}",
+                                field.name
+                            ))
+                        })?;
+                        let data = &mut cloud.columns_mut()[j];
+                        if is_packed_color(&field.name) && field.datatype == FieldType::F32 {
+                            data.push_le_bytes(&(parse_packed_color(text)? as u32).to_le_bytes())?;
+                        } else {
+                            data.push_str(text)
+                                .map_err(|e| PcdError::InvalidData(e.to_string()))?;
+                        }
+                    }
+                }
             }
         }
+        PCDBody::Binary { data, point_size } => {
+            let field_offsets = field_offsets(&header)?;
+            if point_size > 0 {
+                for buf_chunk in data.chunks(point_size) {
+                    for (j, (i, field)) in known.iter().enumerate() {
+                        let size = field.datatype.size();
+                        for k in 0..field.count {
+                            let i_start = field_offsets[*i] + k * size;
+                            cloud.columns_mut()[j]
+                                .push_le_bytes(&buf_chunk[i_start..(i_start + size)])?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if cloud.len() == header.points {
+        cloud.width = header.width as u32;
+        cloud.height = header.height as u32;
+    }
+    Ok(cloud)
+}
+
+/// Decompresses the LZF payload of a binary_compressed PCD and converts its
+/// column-major layout into interleaved points.
+fn decompress_columns(
+    buf: &[u8],
+    header: &PCDHeader,
+    field_sizes: &[usize],
+    data_size: usize,
+) -> Result<Vec<u8>> {
+    if buf.len() < 8 {
+        return Err(PcdError::TruncatedData {
+            expected: 8,
+            found: buf.len(),
+        }
+        .into());
+    }
+    let compressed_size = u32::from_le_bytes(buf[0..4].try_into()?) as usize;
+    let uncompressed_size = u32::from_le_bytes(buf[4..8].try_into()?) as usize;
+    if uncompressed_size != data_size {
+        return Err(PcdError::DecompressionFailed(format!(
+            "uncompressed size {} does not match the {} bytes announced by the header",
+            uncompressed_size, data_size
+        ))
+        .into());
+    }
+    let compressed_end = compressed_size.saturating_add(8);
+    let compressed = buf.get(8..compressed_end).ok_or(PcdError::TruncatedData {
+        expected: compressed_end,
+        found: buf.len(),
+    })?;
+    if data_size == 0 {
+        return Ok(Vec::new());
+    }
+    let res_decomp = lzf::decompress(compressed, uncompressed_size)
+        .map_err(|e| PcdError::DecompressionFailed(e.to_string()))?;
+    if res_decomp.len() != uncompressed_size {
+        return Err(PcdError::DecompressionFailed(format!(
+            "expected {} bytes, got {}",
+            uncompressed_size,
+            res_decomp.len()
+        ))
+        .into());
+    }
+    let field_offsets = field_offsets(header)?;
+    let fsize = *field_offsets.last().unwrap_or(&0);
+    let offsets = [
+        vec![0],
+        field_sizes
+            .iter()
+            .map(|fs| fs * header.points)
+            .cumsum()
+            .collect::<Vec<usize>>(),
+    ]
+    .concat();
+
+    let mut out = vec![0; uncompressed_size];
+    for i in 0..header.points {
+        for (j, fs) in field_sizes.iter().enumerate() {
+            let i_start = i * fsize + field_offsets[j];
+            let i_end = i_start + fs;
+            out[i_start..i_end]
+                .copy_from_slice(&res_decomp[(offsets[j] + i * fs)..(offsets[j] + (i + 1) * fs)]);
+        }
+    }
+    Ok(out)
+}
+
+fn field_values<T: Point>(point: &T, field: &PointField) -> Result<Vec<f64>> {
+    let values = point.field_values(&field.name)?;
+    if values.len() != field.count {
+        return Err(anyhow!(
+            "Field {:?} has {} values but COUNT is {}",
+            field.name,
+            values.len(),
+            field.count
+        ));
     }
-    if header.data == "ascii" {
-        let buf_str = String::from_utf8_lossy(&buf);
-        let mut reader = ReaderBuilder::new()
-            .delimiter(b' ')
-            .from_reader(buf_str.as_bytes());
-        for result in reader.records() {
-            let record = result?;
-            let data = get_data_from_record(&record, &header)?;
-            pointcloud.add_data(data);
+    Ok(values)
+}
+
+fn format_ascii_value(value: f64, field: &PointField) -> String {
+    if is_packed_color(&field.name) {
+        return format!("{}", value as u32);
+    }
+    match field.datatype {
+        FieldType::F32 | FieldType::F64 if value.is_nan() => "nan".to_string(),
+        FieldType::F32 => format!("{}", value as f32),
+        FieldType::F64 => format!("{}", value),
+        _ => format!("{}", value as i64),
+    }
+}
+
+fn encode_binary_value(value: f64, field: &PointField, out: &mut Vec<u8>) {
+    if is_packed_color(&field.name) && field.datatype.size() == 4 {
+        out.extend_from_slice(&(value as u32).to_le_bytes());
+        return;
+    }
+    match field.datatype {
+        FieldType::I8 => out.extend_from_slice(&(value as i8).to_le_bytes()),
+        FieldType::U8 => out.extend_from_slice(&(value as u8).to_le_bytes()),
+        FieldType::I16 => out.extend_from_slice(&(value as i16).to_le_bytes()),
+        FieldType::U16 => out.extend_from_slice(&(value as u16).to_le_bytes()),
+        FieldType::I32 => out.extend_from_slice(&(value as i32).to_le_bytes()),
+        FieldType::U32 => out.extend_from_slice(&(value as u32).to_le_bytes()),
+        FieldType::I64 => out.extend_from_slice(&(value as i64).to_le_bytes()),
+        FieldType::U64 => out.extend_from_slice(&(value as u64).to_le_bytes()),
+        FieldType::F32 => out.extend_from_slice(&(value as f32).to_le_bytes()),
+        FieldType::F64 => out.extend_from_slice(&value.to_le_bytes()),
+    }
+}
+
+/// Compresses `data` with LZF, falling back to literal runs when it does not shrink.
+fn compress_lzf(data: &[u8]) -> Vec<u8> {
+    match lzf::compress(data) {
+        Ok(compressed) => compressed,
+        Err(_) => {
+            let mut literal = Vec::with_capacity(data.len() + data.len() / 32 + 1);
+            for chunk in data.chunks(32) {
+                literal.push((chunk.len() - 1) as u8);
+                literal.extend_from_slice(chunk);
+            }
+            literal
         }
+    }
+}
+
+/// Writes a point cloud in PCD format to any writer, as described in `write_pcd`.
+pub fn write_pcd_to_writer<T, W>(
+    pointcloud: &PointCloud<T>,
+    writer: &mut W,
+    encoding: PCDEncoding,
+) -> Result<()>
+where
+    T: Point,
+    W: Write,
+{
+    let n_points = pointcloud.data.len();
+    write_pcd_values(
+        writer,
+        &T::fields(),
+        (pointcloud.width, pointcloud.height),
+        n_points,
+        encoding,
+        |i, f| field_values(&pointcloud.data[i], f),
+    )
+}
+
+/// Writes the header and data section for `n_points` points whose values are
+/// provided by `values`.
+fn write_pcd_values<W, F>(
+    writer: &mut W,
+    fields: &[PointField],
+    (width, height): (u32, u32),
+    n_points: usize,
+    encoding: PCDEncoding,
+    values: F,
+) -> Result<()>
+where
+    W: Write,
+    F: Fn(usize, &PointField) -> Result<Vec<f64>>,
+{
+    // A layout that no longer matches the number of points is written unorganized.
+    let (width, height) = if height > 1 && width as usize * height as usize == n_points {
+        (width as usize, height as usize)
     } else {
-        for buf_chunk in buf.chunks(fsize) {
-            let data = get_data_from_binary(buf_chunk, &header)?;
-            pointcloud.add_data(data);
+        (n_points, 1)
+    };
+    let join =
+        |f: &dyn Fn(&PointField) -> String| fields.iter().map(f).collect::<Vec<_>>().join(" ");
+    writeln!(writer, "# .PCD v0.7 - Point Cloud Data file format")?;
+    writeln!(writer, "VERSION 0.7")?;
+    writeln!(writer, "FIELDS {}", join(&|f| f.name.clone()))?;
+    writeln!(writer, "SIZE {}", join(&|f| f.datatype.size().to_string()))?;
+    writeln!(
+        writer,
+        "TYPE {}",
+        join(&|f| f.datatype.pcd_type().to_string())
+    )?;
+    writeln!(writer, "COUNT {}", join(&|f| f.count.to_string()))?;
+    writeln!(writer, "WIDTH {}", width)?;
+    writeln!(writer, "HEIGHT {}", height)?;
+    writeln!(writer, "VIEWPOINT 0 0 0 1 0 0 0")?;
+    writeln!(writer, "POINTS {}", n_points)?;
+    writeln!(writer, "DATA {}", encoding.as_str())?;
+
+    match encoding {
+        PCDEncoding::Ascii => {
+            for i in 0..n_points {
+                let mut tokens = Vec::new();
+                for f in fields.iter() {
+                    for value in values(i, f)? {
+                        tokens.push(format_ascii_value(value, f));
+                    }
+                }
+                writeln!(writer, "{}", tokens.join(" "))?;
+            }
+        }
+        PCDEncoding::Binary => {
+            let mut buf = Vec::new();
+            for i in 0..n_points {
+                for f in fields.iter() {
+                    for value in values(i, f)? {
+                        encode_binary_value(value, f, &mut buf);
+                    }
+                }
+            }
+            writer.write_all(&buf)?;
+        }
+        PCDEncoding::BinaryCompressed => {
+            let mut buf = Vec::new();
+            for f in fields.iter() {
+                for i in 0..n_points {
+                    for value in values(i, f)? {
+                        encode_binary_value(value, f, &mut buf);
+                    }
+                }
+            }
+            let compressed = if buf.is_empty() {
+                Vec::new()
+            } else {
+                compress_lzf(&buf)
+            };
+            writer.write_all(&(compressed.len() as u32).to_le_bytes())?;
+            writer.write_all(&(buf.len() as u32).to_le_bytes())?;
+            writer.write_all(&compressed)?;
         }
     }
-    Ok(pointcloud)
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes a `DynamicPointCloud` to a PCD file with its own schema.
+pub fn write_pcd_dynamic(
+    pointcloud: &DynamicPointCloud,
+    filename: &str,
+    encoding: PCDEncoding,
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+    write_pcd_dynamic_to_writer(pointcloud, &mut writer, encoding)
+}
+
+/// Writes a `DynamicPointCloud` in PCD format to any writer.
+pub fn write_pcd_dynamic_to_writer<W: Write>(
+    pointcloud: &DynamicPointCloud,
+    writer: &mut W,
+    encoding: PCDEncoding,
+) -> Result<()> {
+    write_pcd_values(
+        writer,
+        pointcloud.fields(),
+        (pointcloud.width, pointcloud.height),
+        pointcloud.len(),
+        encoding,
+        |i, f| pointcloud.field_values(i, &f.name),
+    )
+}
+
+/// Writes a point cloud to a PCD file.
+///
+/// The header is derived from `Point::fields`, so colors are stored as a packed `rgb`
+/// field and normals as `normal_x`, `normal_y` and `normal_z`. Organized clouds keep
+/// their WIDTH x HEIGHT layout.
+pub fn write_pcd<T>(pointcloud: &PointCloud<T>, filename: &str, encoding: PCDEncoding) -> Result<()>
+where
+    T: Point,
+{
+    let mut writer = BufWriter::new(File::create(filename)?);
+    write_pcd_to_writer(pointcloud, &mut writer, encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::{pack_rgb, unpack_rgb, PointXYZ, PointXYZRGBNormal};
+    use nalgebra::Vector3;
+
+    const HEADER: &str = "VERSION 0.7\nFIELDS x y z\nSIZE 4 4 4\nTYPE F F F\nCOUNT 1 1 1\n";
+
+    fn read(bytes: &[u8]) -> Result<PointCloud<PointXYZ<f32>>> {
+        read_pcd_from_bytes(bytes)
+    }
+
+    fn pcd_error(bytes: &[u8]) -> PcdError {
+        let err = match read(bytes) {
+            Ok(_) => panic!("malformed input was accepted"),
+            Err(err) => err,
+        };
+        err.downcast_ref::<PcdError>()
+            .unwrap_or_else(|| panic!("not a PcdError: {}", err))
+            .clone()
+    }
+
+    fn sample_cloud() -> PointCloud<PointXYZ<f32>> {
+        PointCloud::from_point_vec(
+            (0..20)
+                .map(|i| {
+                    let t = i as f32;
+                    Vector3::new(t, 0.5 * t, -t)
+                })
+                .collect(),
+        )
+    }
+
+    fn encode(encoding: PCDEncoding) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_pcd_to_writer(&sample_cloud(), &mut bytes, encoding).unwrap();
+        bytes
+    }
+
+    fn colored_cloud() -> PointCloud<PointXYZRGBNormal<f32, f32, f32>> {
+        let mut pointcloud = PointCloud::new();
+        for i in 0..6 {
+            let t = i as f32;
+            pointcloud.add_data(PointXYZRGBNormal {
+                point: Vector3::new(0.25 * t, -0.5 * t, 1.0 + t),
+                color: Vector3::new(t * 40.0 / 255.0, 1.0, 0.0),
+                normal: Vector3::new(0.0, 0.0, 1.0),
+            });
+        }
+        pointcloud
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let mut pointcloud = colored_cloud();
+        pointcloud.width = 3;
+        pointcloud.height = 2;
+        for encoding in [
+            PCDEncoding::Ascii,
+            PCDEncoding::Binary,
+            PCDEncoding::BinaryCompressed,
+        ]
+        .iter()
+        {
+            let mut bytes = Vec::new();
+            write_pcd_to_writer(&pointcloud, &mut bytes, *encoding).unwrap();
+            let loaded: PointCloud<PointXYZRGBNormal<f32, f32, f32>> =
+                read_pcd_from_bytes(&bytes).unwrap();
+            assert_eq!((loaded.width, loaded.height), (3, 2), "{:?}", encoding);
+            assert_eq!(loaded.len(), pointcloud.len());
+            for (a, b) in pointcloud.data.iter().zip(loaded.data.iter()) {
+                assert_eq!(a.point, b.point, "{:?}", encoding);
+                assert_eq!(a.color, b.color, "{:?}", encoding);
+                assert_eq!(a.normal, b.normal, "{:?}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn stale_layout_is_written_unorganized() {
+        let mut pointcloud = colored_cloud();
+        pointcloud.width = 4;
+        pointcloud.height = 2;
+        let mut bytes = Vec::new();
+        write_pcd_to_writer(&pointcloud, &mut bytes, PCDEncoding::Binary).unwrap();
+        let header = read_pcd_header_from_reader(&mut &bytes[..]).unwrap();
+        assert_eq!((header.width, header.height, header.points), (6, 1, 6));
+        let loaded: PointCloud<PointXYZ<f32>> = read_pcd_from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.len(), 6);
+    }
+
+    #[test]
+    fn organized_bunny() {
+        let path = "examples/data/bunny.pcd";
+        let pointcloud: PointCloud<PointXYZ<f32>> = read_pcd_organized(path).unwrap();
+        assert!(pointcloud.is_organized());
+        assert_eq!((pointcloud.width, pointcloud.height), (512, 400));
+        assert_eq!(pointcloud.len(), 512 * 400);
+        let n_invalid = pointcloud
+            .data
+            .iter()
+            .filter(|p| p.point.iter().all(|v| v.is_nan()))
+            .count();
+        assert_eq!(n_invalid, 512 * 400 - 40256);
+        assert!(pointcloud.at(0, 0).point[0].is_nan());
+        assert_eq!(pointcloud.remove_non_finite_points().len(), 40256);
+
+        let pointcloud: PointCloud<PointXYZ<f32>> = read_pcd(path).unwrap();
+        assert_eq!((pointcloud.width, pointcloud.height), (512, 400));
+        assert!(pointcloud
+            .data
+            .iter()
+            .all(|p| p.point.iter().all(|v| v.is_finite())));
+        assert_eq!(pointcloud.at(0, 0).point, Vector3::zeros());
+    }
+
+    #[test]
+    fn typed_integer_fields() {
+        // x is I16, y is U32, z is I64; `flags` (U8) and `h` (unsupported F16) are
+        // skipped by their declared width.
+        let header = "VERSION 0.7\nFIELDS x flags y h z\nSIZE 2 1 4 2 8\nTYPE I U U F I\n\
+                      COUNT 1 1 1 1 1\nWIDTH 2\n";
+        let ascii = format!(
+            "{}DATA ascii\n-3 255 70000 0 -9\n12 1 4294967295 0 8\n",
+            header
+        );
+        let mut binary = format!("{}DATA binary\n", header).into_bytes();
+        for (x, flags, y, z) in [(-3i16, 255u8, 70000u32, -9i64), (12, 1, u32::MAX, 8)].iter() {
+            binary.extend_from_slice(&x.to_le_bytes());
+            binary.push(*flags);
+            binary.extend_from_slice(&y.to_le_bytes());
+            binary.extend_from_slice(&[0, 0]);
+            binary.extend_from_slice(&z.to_le_bytes());
+        }
+        for bytes in [ascii.as_bytes(), &binary[..]].iter() {
+            let pointcloud: PointCloud<PointXYZ<f64>> = read_pcd_from_bytes(bytes).unwrap();
+            assert_eq!(pointcloud.len(), 2);
+            assert_eq!(pointcloud.item(0).point, Vector3::new(-3.0, 70000.0, -9.0));
+            assert_eq!(
+                pointcloud.item(1).point,
+                Vector3::new(12.0, u32::MAX as f64, 8.0)
+            );
+        }
+    }
+
+    #[test]
+    fn packed_colors_and_normals() {
+        let packed = pack_rgb([255, 128, 0]);
+        assert_eq!(unpack_rgb(packed), [255, 128, 0]);
+        let header = "VERSION 0.7\nFIELDS x y z rgb normal_x normal_y normal_z\n\
+                      SIZE 4 4 4 4 4 4 4\nTYPE F F F F F F F\nCOUNT 1 1 1 1 1 1 1\nWIDTH 2\n";
+        // PCL writes rgb as the float sharing the bits of the packed word; integers are
+        // accepted too.
+        let ascii = format!(
+            "{}DATA ascii\n1 2 3 {} 0 0 1\n4 5 6 {} 0 1 0\n",
+            header,
+            f32::from_bits(packed),
+            packed
+        );
+        let rgba = header
+            .replace("rgb", "rgba")
+            .replace("TYPE F F F F", "TYPE F F F U");
+        let mut binary = format!("{}DATA binary\n", rgba).into_bytes();
+        for (p, n) in [
+            ([1f32, 2., 3.], [0f32, 0., 1.]),
+            ([4., 5., 6.], [0., 1., 0.]),
+        ]
+        .iter()
+        {
+            for v in p.iter() {
+                binary.extend_from_slice(&v.to_le_bytes());
+            }
+            binary.extend_from_slice(&(packed | 0xff00_0000).to_le_bytes());
+            for v in n.iter() {
+                binary.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        for bytes in [ascii.as_bytes(), &binary[..]].iter() {
+            let pointcloud: PointCloud<PointXYZRGBNormal<f32, u8, f32>> =
+                read_pcd_from_bytes(bytes).unwrap();
+            assert_eq!(pointcloud.len(), 2);
+            for point in pointcloud.data.iter() {
+                assert_eq!(point.color, Vector3::new(255, 128, 0));
+            }
+            assert_eq!(pointcloud.item(0).normal, Vector3::new(0.0, 0.0, 1.0));
+            assert_eq!(pointcloud.item(1).point, Vector3::new(4.0, 5.0, 6.0));
+            assert_eq!(pointcloud.item(1).normal, Vector3::new(0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn empty_input() {
+        assert!(read(b"").is_err());
+    }
+
+    #[test]
+    fn bad_version() {
+        let text = HEADER.replace("0.7", "0.9") + "WIDTH 1\nDATA ascii\n1 2 3\n";
+        assert!(matches!(
+            pcd_error(text.as_bytes()),
+            PcdError::UnsupportedVersion(_)
+        ));
+    }
+
+    #[test]
+    fn missing_data() {
+        let text = HEADER.to_string() + "WIDTH 1\nPOINTS 1\n";
+        assert!(matches!(
+            pcd_error(text.as_bytes()),
+            PcdError::MissingField("DATA")
+        ));
+    }
+
+    #[test]
+    fn overflowing_count() {
+        let text = HEADER.replace("COUNT 1 1 1", "COUNT 1 1 4611686018427387904")
+            + "WIDTH 1\nDATA binary\n";
+        let mut bytes = text.into_bytes();
+        bytes.extend_from_slice(&[0; 16]);
+        assert!(matches!(
+            pcd_error(&bytes),
+            PcdError::InvalidHeaderValue { .. }
+        ));
+        let text = HEADER.replace("COUNT 1 1 1", "COUNT 1 1 18446744073709551615")
+            + "WIDTH 1\nDATA ascii\n1 2 3\n";
+        assert!(matches!(
+            pcd_error(text.as_bytes()),
+            PcdError::InvalidHeaderValue { entry: "COUNT", .. }
+        ));
+    }
+
+    #[test]
+    fn overflowing_size() {
+        let text = HEADER.replace("SIZE 4 4 4", "SIZE 4 4 18446744073709551615")
+            + "WIDTH 1\nDATA binary\n";
+        let mut bytes = text.into_bytes();
+        bytes.extend_from_slice(&[0; 16]);
+        assert!(matches!(
+            pcd_error(&bytes),
+            PcdError::InvalidHeaderValue { .. }
+        ));
+    }
+
+    #[test]
+    fn overflowing_point_count() {
+        let text = HEADER.to_string() + "WIDTH 4294967296\nHEIGHT 4294967296\nDATA binary\n";
+        assert!(matches!(
+            pcd_error(text.as_bytes()),
+            PcdError::InvalidHeaderValue { entry: "WIDTH", .. }
+        ));
+        let text = HEADER.to_string() + "WIDTH 2305843009213693952\nDATA binary\n";
+        assert!(matches!(
+            pcd_error(text.as_bytes()),
+            PcdError::InvalidHeaderValue {
+                entry: "POINTS",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn truncated_binary_compressed() {
+        let bytes = encode(PCDEncoding::BinaryCompressed);
+        assert_eq!(read(&bytes).unwrap().len(), 20);
+        for len in find_data_start(&bytes)..bytes.len() {
+            assert!(read(&bytes[..len]).is_err(), "accepted {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn corrupted_input_does_not_panic() {
+        for encoding in [
+            PCDEncoding::Ascii,
+            PCDEncoding::Binary,
+            PCDEncoding::BinaryCompressed,
+        ]
+        .iter()
+        {
+            let bytes = encode(*encoding);
+            for len in 0..bytes.len() {
+                let _ = read(&bytes[..len]);
+                let _ = read_pcd_dynamic_from_reader(&bytes[..len]);
+            }
+            let mut state: u32 = 12345;
+            for _ in 0..500 {
+                let mut corrupted = bytes.clone();
+                for _ in 0..4 {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    let i = (state >> 8) as usize % corrupted.len();
+                    corrupted[i] = (state >> 16) as u8;
+                }
+                let _ = read(&corrupted);
+                let _ = read_pcd_dynamic_from_reader(&corrupted[..]);
+            }
+        }
+    }
+
+    fn find_data_start(bytes: &[u8]) -> usize {
+        let marker = b"DATA binary_compressed\n";
+        bytes
+            .windows(marker.len())
+            .position(|w| w == marker)
+            .unwrap()
+            + marker.len()
+    }
 }
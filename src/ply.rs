@@ -0,0 +1,639 @@
+use super::pointcloud::{is_packed_color, pack_rgb, unpack_rgb, FieldType, Point, PointCloud};
+use anyhow::{anyhow, Result};
+use core::convert::TryInto;
+use num_traits::NumAssign;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+/// Encoding of the body of a PLY file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PLYEncoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+impl PLYEncoding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PLYEncoding::Ascii => "ascii",
+            PLYEncoding::BinaryLittleEndian => "binary_little_endian",
+            PLYEncoding::BinaryBigEndian => "binary_big_endian",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PLYProperty {
+    Scalar {
+        name: String,
+        datatype: FieldType,
+    },
+    List {
+        name: String,
+        count_type: FieldType,
+        item_type: FieldType,
+    },
+}
+
+#[derive(Debug)]
+struct PLYElement {
+    name: String,
+    count: usize,
+    properties: Vec<PLYProperty>,
+}
+
+#[derive(Debug)]
+struct PLYHeader {
+    encoding: PLYEncoding,
+    elements: Vec<PLYElement>,
+}
+
+fn parse_ply_type(name: &str) -> Result<FieldType> {
+    match name {
+        "char" | "int8" => Ok(FieldType::I8),
+        "uchar" | "uint8" => Ok(FieldType::U8),
+        "short" | "int16" => Ok(FieldType::I16),
+        "ushort" | "uint16" => Ok(FieldType::U16),
+        "int" | "int32" => Ok(FieldType::I32),
+        "uint" | "uint32" => Ok(FieldType::U32),
+        "float" | "float32" => Ok(FieldType::F32),
+        "double" | "float64" => Ok(FieldType::F64),
+        &_ => Err(anyhow!(format!("Invalid PLY property type {:?}", name))),
+    }
+}
+
+fn ply_type_name(datatype: FieldType) -> &'static str {
+    match datatype {
+        FieldType::I8 => "char",
+        FieldType::U8 => "uchar",
+        FieldType::I16 => "short",
+        FieldType::U16 => "ushort",
+        FieldType::I32 => "int",
+        FieldType::U32 => "uint",
+        FieldType::F32 => "float",
+        // PLY has no 64-bit integers; values already go through f64.
+        FieldType::I64 | FieldType::U64 | FieldType::F64 => "double",
+    }
+}
+
+/// Narrowest type holding the element count of a list property.
+fn list_count_type(count: usize) -> FieldType {
+    if count <= u8::MAX as usize {
+        FieldType::U8
+    } else if count <= u16::MAX as usize {
+        FieldType::U16
+    } else {
+        FieldType::U32
+    }
+}
+
+fn parse_header<R: BufRead>(reader: &mut R) -> Result<PLYHeader> {
+    let mut buf = String::new();
+    reader.read_line(&mut buf)?;
+    if buf.trim_end() != "ply" {
+        return Err(anyhow!("Missing PLY magic number"));
+    }
+    let mut encoding = None;
+    let mut elements: Vec<PLYElement> = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_line(&mut buf)? == 0 {
+            return Err(anyhow!("Missing end_header in PLY header"));
+        }
+        let v: Vec<&str> = buf.split_whitespace().collect();
+        match v.as_slice() {
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => PLYEncoding::Ascii,
+                    "binary_little_endian" => PLYEncoding::BinaryLittleEndian,
+                    "binary_big_endian" => PLYEncoding::BinaryBigEndian,
+                    &_ => return Err(anyhow!(format!("Invalid PLY format {:?}", format))),
+                })
+            }
+            ["element", name, count] => elements.push(PLYElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| anyhow!(format!("Invalid PLY element count {:?}", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => elements
+                .last_mut()
+                .ok_or_else(|| anyhow!("PLY property declared before any element"))?
+                .properties
+                .push(PLYProperty::List {
+                    name: name.to_string(),
+                    count_type: parse_ply_type(count_type)?,
+                    item_type: parse_ply_type(item_type)?,
+                }),
+            ["property", datatype, name] => elements
+                .last_mut()
+                .ok_or_else(|| anyhow!("PLY property declared before any element"))?
+                .properties
+                .push(PLYProperty::Scalar {
+                    name: name.to_string(),
+                    datatype: parse_ply_type(datatype)?,
+                }),
+            ["end_header"] => break,
+            _ => (),
+        }
+    }
+    Ok(PLYHeader {
+        encoding: encoding.ok_or_else(|| anyhow!("Missing format in PLY header"))?,
+        elements,
+    })
+}
+
+/// Sequential reader over the values of a PLY body.
+enum PLYBody<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl<'a> PLYBody<'a> {
+    fn next_value(&mut self, datatype: FieldType) -> Result<f64> {
+        match self {
+            PLYBody::Ascii(tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| anyhow!("Unexpected end of PLY data"))?;
+                token
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!(format!("Invalid PLY value {:?}", token)))
+            }
+            PLYBody::Binary { data, big_endian } => {
+                let size = datatype.size();
+                if data.len() < size {
+                    return Err(anyhow!("Unexpected end of PLY data"));
+                }
+                let (bytes, rest) = data.split_at(size);
+                *data = rest;
+                let mut buf = [0u8; 8];
+                buf[..size].copy_from_slice(bytes);
+                if *big_endian {
+                    buf[..size].reverse();
+                }
+                let value = match datatype {
+                    FieldType::I8 => buf[0] as i8 as f64,
+                    FieldType::U8 => buf[0] as f64,
+                    FieldType::I16 => i16::from_le_bytes(buf[..2].try_into()?) as f64,
+                    FieldType::U16 => u16::from_le_bytes(buf[..2].try_into()?) as f64,
+                    FieldType::I32 => i32::from_le_bytes(buf[..4].try_into()?) as f64,
+                    FieldType::U32 => u32::from_le_bytes(buf[..4].try_into()?) as f64,
+                    FieldType::I64 => i64::from_le_bytes(buf) as f64,
+                    FieldType::U64 => u64::from_le_bytes(buf) as f64,
+                    FieldType::F32 => f32::from_le_bytes(buf[..4].try_into()?) as f64,
+                    FieldType::F64 => f64::from_le_bytes(buf),
+                };
+                Ok(value)
+            }
+        }
+    }
+}
+
+/// Maps a PLY vertex property name onto the field names used by `Point`.
+fn point_field_name(name: &str) -> &str {
+    match name {
+        "nx" => "normal_x",
+        "ny" => "normal_y",
+        "nz" => "normal_z",
+        &_ => name,
+    }
+}
+
+fn color_channel(name: &str) -> Option<usize> {
+    match name {
+        "red" | "r" | "diffuse_red" => Some(0),
+        "green" | "g" | "diffuse_green" => Some(1),
+        "blue" | "b" | "diffuse_blue" => Some(2),
+        &_ => None,
+    }
+}
+
+fn color_to_u8(value: f64, datatype: FieldType) -> u8 {
+    match datatype {
+        FieldType::F32 | FieldType::F64 => (value.clamp(0.0, 1.0) * 255.0).round() as u8,
+        FieldType::U16 => (value / 257.0).round() as u8,
+        _ => value.clamp(0.0, 255.0) as u8,
+    }
+}
+
+fn read_vertex<T: Point + Default>(element: &PLYElement, body: &mut PLYBody) -> Result<T> {
+    let mut data = T::default();
+    let mut rgb = None;
+    for property in element.properties.iter() {
+        match property {
+            PLYProperty::Scalar { name, datatype } => {
+                let value = body.next_value(*datatype)?;
+                if let Some(channel) = color_channel(name) {
+                    rgb.get_or_insert([0u8; 3])[channel] = color_to_u8(value, *datatype);
+                } else {
+                    // Properties the point type does not carry are ignored.
+                    let _ = data.set_field_value(point_field_name(name), value);
+                }
+            }
+            PLYProperty::List {
+                name,
+                count_type,
+                item_type,
+            } => {
+                let n = body.next_value(*count_type)? as usize;
+                let values = (0..n)
+                    .map(|_| body.next_value(*item_type))
+                    .collect::<Result<Vec<_>>>()?;
+                let _ = data.set_field_values(point_field_name(name), &values);
+            }
+        }
+    }
+    if let Some(rgb) = rgb {
+        let _ = data.set_field_value("rgb", pack_rgb(rgb) as f64);
+    }
+    Ok(data)
+}
+
+fn skip_element(element: &PLYElement, body: &mut PLYBody) -> Result<()> {
+    for property in element.properties.iter() {
+        match property {
+            PLYProperty::Scalar { datatype, .. } => {
+                body.next_value(*datatype)?;
+            }
+            PLYProperty::List {
+                count_type,
+                item_type,
+                ..
+            } => {
+                let n = body.next_value(*count_type)? as usize;
+                for _ in 0..n {
+                    body.next_value(*item_type)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the `vertex` element of a PLY file into a point cloud.
+///
+/// `x y z`, `nx ny nz` and `red green blue` properties are mapped onto the point,
+/// normal and color fields. Other elements such as faces are skipped.
+pub fn read_ply<T>(filename: &str) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_ply_from_reader(BufReader::new(File::open(filename)?))
+}
+
+/// Same as `read_ply`, reading the PLY content from any buffered source.
+pub fn read_ply_from_reader<T, R>(mut reader: R) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+    R: BufRead,
+{
+    let header = parse_header(&mut reader)?;
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    let text;
+    let mut body = match header.encoding {
+        PLYEncoding::Ascii => {
+            text = String::from_utf8_lossy(&buf);
+            PLYBody::Ascii(text.split_whitespace())
+        }
+        PLYEncoding::BinaryLittleEndian => PLYBody::Binary {
+            data: &buf,
+            big_endian: false,
+        },
+        PLYEncoding::BinaryBigEndian => PLYBody::Binary {
+            data: &buf,
+            big_endian: true,
+        },
+    };
+
+    let mut pointcloud = PointCloud::<T>::new();
+    for element in header.elements.iter() {
+        if element.name == "vertex" {
+            for _ in 0..element.count {
+                pointcloud.add_data(read_vertex(element, &mut body)?);
+            }
+            // Elements after the vertices are not needed.
+            break;
+        }
+        for _ in 0..element.count {
+            skip_element(element, &mut body)?;
+        }
+    }
+    Ok(pointcloud)
+}
+
+/// Property written for one field of the point type.
+struct PLYOutputProperty {
+    field: String,
+    datatype: FieldType,
+    count: usize,
+}
+
+fn output_properties<T: Point>() -> Vec<(String, PLYOutputProperty)> {
+    let mut properties = Vec::new();
+    for f in T::fields() {
+        if is_packed_color(&f.name) {
+            for channel in ["red", "green", "blue"].iter() {
+                properties.push((
+                    channel.to_string(),
+                    PLYOutputProperty {
+                        field: f.name.clone(),
+                        datatype: FieldType::U8,
+                        count: 1,
+                    },
+                ));
+            }
+            continue;
+        }
+        let name = match f.name.as_str() {
+            "normal_x" => "nx".to_string(),
+            "normal_y" => "ny".to_string(),
+            "normal_z" => "nz".to_string(),
+            &_ => f.name.clone(),
+        };
+        properties.push((
+            name,
+            PLYOutputProperty {
+                field: f.name.clone(),
+                datatype: f.datatype,
+                count: f.count,
+            },
+        ));
+    }
+    properties
+}
+
+fn encode_value(value: f64, datatype: FieldType, encoding: PLYEncoding, out: &mut Vec<u8>) {
+    let mut bytes = match datatype {
+        FieldType::I8 => (value as i8).to_le_bytes().to_vec(),
+        FieldType::U8 => (value as u8).to_le_bytes().to_vec(),
+        FieldType::I16 => (value as i16).to_le_bytes().to_vec(),
+        FieldType::U16 => (value as u16).to_le_bytes().to_vec(),
+        FieldType::I32 => (value as i32).to_le_bytes().to_vec(),
+        FieldType::U32 => (value as u32).to_le_bytes().to_vec(),
+        FieldType::F32 => (value as f32).to_le_bytes().to_vec(),
+        FieldType::I64 | FieldType::U64 | FieldType::F64 => value.to_le_bytes().to_vec(),
+    };
+    if encoding == PLYEncoding::BinaryBigEndian {
+        bytes.reverse();
+    }
+    out.extend_from_slice(&bytes);
+}
+
+fn format_value(value: f64, datatype: FieldType) -> String {
+    match datatype {
+        FieldType::F32 => format!("{}", value as f32),
+        FieldType::I64 | FieldType::U64 | FieldType::F64 => format!("{}", value),
+        _ => format!("{}", value as i64),
+    }
+}
+
+/// Writes a point cloud as the `vertex` element of a PLY file.
+///
+/// Colors are stored as `red green blue` uchar properties and normals as `nx ny nz`.
+/// Fields with `count > 1` are stored as list properties, and 64-bit integer fields as
+/// `double`, since PLY has no 64-bit integer type.
+pub fn write_ply<T>(pointcloud: &PointCloud<T>, filename: &str, encoding: PLYEncoding) -> Result<()>
+where
+    T: Point,
+{
+    let mut writer = BufWriter::new(File::create(filename)?);
+    write_ply_to_writer(pointcloud, &mut writer, encoding)
+}
+
+/// Writes a point cloud in PLY format to any writer, as described in `write_ply`.
+pub fn write_ply_to_writer<T, W>(
+    pointcloud: &PointCloud<T>,
+    writer: &mut W,
+    encoding: PLYEncoding,
+) -> Result<()>
+where
+    T: Point,
+    W: Write,
+{
+    let properties = output_properties::<T>();
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {} 1.0", encoding.as_str())?;
+    writeln!(writer, "element vertex {}", pointcloud.data.len())?;
+    for (name, p) in properties.iter() {
+        if p.count == 1 {
+            writeln!(writer, "property {} {}", ply_type_name(p.datatype), name)?;
+        } else {
+            writeln!(
+                writer,
+                "property list {} {} {}",
+                ply_type_name(list_count_type(p.count)),
+                ply_type_name(p.datatype),
+                name
+            )?;
+        }
+    }
+    writeln!(writer, "end_header")?;
+
+    let mut buf = Vec::new();
+    let mut tokens = Vec::new();
+    for point in pointcloud.data.iter() {
+        tokens.clear();
+        for (name, p) in properties.iter() {
+            let values = if is_packed_color(&p.field) {
+                let rgb = unpack_rgb(point.field_value(&p.field)? as u32);
+                vec![rgb[color_channel(name).unwrap_or(0)] as f64]
+            } else {
+                point.field_values(&p.field)?
+            };
+            if values.len() != p.count {
+                return Err(anyhow!(format!(
+                    "Field {:?} has COUNT {} but holds {} values",
+                    p.field,
+                    p.count,
+                    values.len()
+                )));
+            }
+            if p.count != 1 {
+                if encoding == PLYEncoding::Ascii {
+                    tokens.push(p.count.to_string());
+                } else {
+                    encode_value(p.count as f64, list_count_type(p.count), encoding, &mut buf);
+                }
+            }
+            for value in values {
+                if encoding == PLYEncoding::Ascii {
+                    tokens.push(format_value(value, p.datatype));
+                } else {
+                    encode_value(value, p.datatype, encoding, &mut buf);
+                }
+            }
+        }
+        if encoding == PLYEncoding::Ascii {
+            writeln!(writer, "{}", tokens.join(" "))?;
+        }
+    }
+    writer.write_all(&buf)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::{PointField, PointXYZRGBNormal};
+    use nalgebra::Vector3;
+
+    const ENCODINGS: [PLYEncoding; 3] = [
+        PLYEncoding::Ascii,
+        PLYEncoding::BinaryLittleEndian,
+        PLYEncoding::BinaryBigEndian,
+    ];
+
+    /// Point with a 64-bit id and a histogram longer than a uchar list count.
+    #[derive(Clone, Debug, PartialEq)]
+    struct PointHistogram {
+        point: Vector3<f32>,
+        id: i64,
+        histogram: Vec<f32>,
+    }
+
+    const BINS: usize = 300;
+
+    impl Default for PointHistogram {
+        fn default() -> Self {
+            PointHistogram {
+                point: Vector3::zeros(),
+                id: 0,
+                histogram: vec![0.0; BINS],
+            }
+        }
+    }
+
+    impl Point for PointHistogram {
+        type Item = f32;
+        fn from_point(point: Vector3<f32>) -> Self {
+            PointHistogram {
+                point,
+                ..Default::default()
+            }
+        }
+        fn xyz(&self) -> &Vector3<f32> {
+            &self.point
+        }
+        fn xyz_mut(&mut self) -> &mut Vector3<f32> {
+            &mut self.point
+        }
+        fn fields() -> Vec<PointField> {
+            let mut fields = vec![
+                PointField::new("x", FieldType::F32),
+                PointField::new("y", FieldType::F32),
+                PointField::new("z", FieldType::F32),
+                PointField::new("id", FieldType::I64),
+            ];
+            fields.push(PointField {
+                name: "histogram".to_string(),
+                datatype: FieldType::F32,
+                count: BINS,
+            });
+            fields
+        }
+        fn field_value(&self, name: &str) -> Result<f64> {
+            match name {
+                "id" => Ok(self.id as f64),
+                &_ => Ok(self.point_field(name)? as f64),
+            }
+        }
+        fn set_field_value(&mut self, name: &str, value: f64) -> Result<()> {
+            match name {
+                "id" => self.id = value as i64,
+                &_ => *self.point_field_mut(name)? = value as f32,
+            }
+            Ok(())
+        }
+        fn field_values(&self, name: &str) -> Result<Vec<f64>> {
+            match name {
+                "histogram" => Ok(self.histogram.iter().map(|v| *v as f64).collect()),
+                &_ => Ok(vec![self.field_value(name)?]),
+            }
+        }
+        fn set_field_values(&mut self, name: &str, values: &[f64]) -> Result<()> {
+            match (name, values) {
+                ("histogram", _) if values.len() == BINS => {
+                    self.histogram = values.iter().map(|v| *v as f32).collect();
+                    Ok(())
+                }
+                (_, [value]) => self.set_field_value(name, *value),
+                _ => Err(anyhow!("invalid values")),
+            }
+        }
+    }
+
+    fn round_trip<T: Point + Default>(
+        pointcloud: &PointCloud<T>,
+        encoding: PLYEncoding,
+    ) -> PointCloud<T>
+    where
+        <T as Point>::Item: NumAssign,
+    {
+        let mut bytes = Vec::new();
+        write_ply_to_writer(pointcloud, &mut bytes, encoding).unwrap();
+        read_ply_from_reader(&bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn colored_round_trip() {
+        let mut pointcloud = PointCloud::<PointXYZRGBNormal<f64, u8, f32>>::new();
+        for i in 0..5 {
+            let t = i as f64;
+            pointcloud.add_data(PointXYZRGBNormal {
+                point: Vector3::new(t * 0.1, -t, 1e-3 * t),
+                color: Vector3::new(i as u8 * 50, 255, 3),
+                normal: Vector3::new(0.6, 0.0, 0.8),
+            });
+        }
+        for encoding in ENCODINGS.iter() {
+            let loaded = round_trip(&pointcloud, *encoding);
+            assert_eq!(loaded.len(), 5);
+            for (a, b) in pointcloud.data.iter().zip(loaded.data.iter()) {
+                assert_eq!(a.point, b.point, "{:?}", encoding);
+                assert_eq!(a.color, b.color, "{:?}", encoding);
+                assert_eq!(a.normal, b.normal, "{:?}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn long_list_and_64_bit_round_trip() {
+        let mut pointcloud = PointCloud::<PointHistogram>::new();
+        for i in 0..3 {
+            pointcloud.add_data(PointHistogram {
+                point: Vector3::new(i as f32, 0.5, -2.0),
+                id: (1 << 40) + i as i64,
+                histogram: (0..BINS).map(|j| (i * BINS + j) as f32).collect(),
+            });
+        }
+        for encoding in ENCODINGS.iter() {
+            let loaded = round_trip(&pointcloud, *encoding);
+            assert_eq!(loaded.data, pointcloud.data, "{:?}", encoding);
+        }
+        let mut bytes = Vec::new();
+        write_ply_to_writer(&pointcloud, &mut bytes, PLYEncoding::Ascii).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.contains("property double id"));
+        assert!(text.contains("property list ushort float histogram"));
+    }
+
+    #[test]
+    fn lists_and_other_elements_are_skipped() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\n\
+                    property list uchar int tags\nproperty float y\nproperty float z\n\
+                    element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                    1 2 7 8 2 3\n4 0 5 6\n3 0 1 1\n";
+        let pointcloud: PointCloud<PointXYZRGBNormal<f32, u8, f32>> =
+            read_ply_from_reader(text.as_bytes()).unwrap();
+        assert_eq!(pointcloud.len(), 2);
+        assert_eq!(pointcloud.item(0).point, Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(pointcloud.item(1).point, Vector3::new(4.0, 5.0, 6.0));
+    }
+}
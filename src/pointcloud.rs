@@ -1,8 +1,11 @@
 extern crate nalgebra as na;
 use anyhow::*;
 use nalgebra::base::Scalar;
-use nalgebra::{ClosedAdd, Matrix3, Matrix4, RealField, Vector3};
-use num_traits::{Float, NumAssign, Zero};
+use nalgebra::{
+    ClosedAdd, Isometry3, Matrix3, Matrix4, RealField, Rotation3, Similarity3, Translation3,
+    UnitQuaternion, Vector3,
+};
+use num_traits::{Float, NumAssign, NumCast, ToPrimitive, Zero};
 use std::any::Any;
 use std::fmt::Debug;
 use std::marker::PhantomData;
@@ -11,6 +14,219 @@ use std::ops::{Add, Div};
 pub trait FloatData: Float + Debug + Any {}
 impl<T: Float + Debug + Any> FloatData for T {}
 
+/// Scalar type of a color channel.
+///
+/// Floating point channels are normalized to `[0, 1]`, `u8` channels hold `[0, 255]`.
+pub trait ColorData: Scalar + Copy {
+    fn from_u8(value: u8) -> Self;
+    fn to_u8(&self) -> u8;
+}
+
+impl ColorData for u8 {
+    fn from_u8(value: u8) -> Self {
+        value
+    }
+    fn to_u8(&self) -> u8 {
+        *self
+    }
+}
+
+impl ColorData for f32 {
+    fn from_u8(value: u8) -> Self {
+        value as f32 / 255.0
+    }
+    fn to_u8(&self) -> u8 {
+        (self.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+}
+
+impl ColorData for f64 {
+    fn from_u8(value: u8) -> Self {
+        value as f64 / 255.0
+    }
+    fn to_u8(&self) -> u8 {
+        (self.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+}
+
+/// Packs an 8-bit color into the PCL `rgb` layout (`0x00RRGGBB`).
+pub fn pack_rgb(rgb: [u8; 3]) -> u32 {
+    ((rgb[0] as u32) << 16) | ((rgb[1] as u32) << 8) | (rgb[2] as u32)
+}
+
+/// Unpacks a PCL `rgb`/`rgba` value into its red, green and blue components.
+pub fn unpack_rgb(packed: u32) -> [u8; 3] {
+    [
+        ((packed >> 16) & 0xff) as u8,
+        ((packed >> 8) & 0xff) as u8,
+        (packed & 0xff) as u8,
+    ]
+}
+
+/// Primitive type of a serialized point field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+}
+
+impl FieldType {
+    /// Size of one element in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            FieldType::I8 | FieldType::U8 => 1,
+            FieldType::I16 | FieldType::U16 => 2,
+            FieldType::I32 | FieldType::U32 | FieldType::F32 => 4,
+            FieldType::I64 | FieldType::U64 | FieldType::F64 => 8,
+        }
+    }
+    /// Whether the type holds floating point values.
+    pub fn is_float(&self) -> bool {
+        matches!(self, FieldType::F32 | FieldType::F64)
+    }
+    /// Floating point type with the same size as `T`.
+    pub fn float_of<T>() -> FieldType {
+        if std::mem::size_of::<T>() == 8 {
+            FieldType::F64
+        } else {
+            FieldType::F32
+        }
+    }
+    /// Builds the type from a PCD `TYPE` character and `SIZE`.
+    pub fn from_pcd(field_type: &str, size: usize) -> Option<FieldType> {
+        match (field_type, size) {
+            ("I", 1) => Some(FieldType::I8),
+            ("U", 1) => Some(FieldType::U8),
+            ("I", 2) => Some(FieldType::I16),
+            ("U", 2) => Some(FieldType::U16),
+            ("I", 4) => Some(FieldType::I32),
+            ("U", 4) => Some(FieldType::U32),
+            ("I", 8) => Some(FieldType::I64),
+            ("U", 8) => Some(FieldType::U64),
+            ("F", 4) => Some(FieldType::F32),
+            ("F", 8) => Some(FieldType::F64),
+            _ => None,
+        }
+    }
+    /// PCD `TYPE` character.
+    pub fn pcd_type(&self) -> &'static str {
+        match self {
+            FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64 => "I",
+            FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64 => "U",
+            FieldType::F32 | FieldType::F64 => "F",
+        }
+    }
+}
+
+/// Primitive type that can be stored in a point field.
+pub trait FieldScalar: NumCast + ToPrimitive + Zero + Copy {
+    const FIELD_TYPE: FieldType;
+}
+
+macro_rules! impl_field_scalar {
+    ($($t:ty => $v:ident),*) => {
+        $(impl FieldScalar for $t {
+            const FIELD_TYPE: FieldType = FieldType::$v;
+        })*
+    };
+}
+
+impl_field_scalar!(
+    i8 => I8, u8 => U8, i16 => I16, u16 => U16, i32 => I32,
+    u32 => U32, i64 => I64, u64 => U64, f32 => F32, f64 => F64
+);
+
+/// Description of a named field stored in a point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PointField {
+    pub name: String,
+    pub datatype: FieldType,
+    pub count: usize,
+}
+
+impl PointField {
+    pub fn new(name: &str, datatype: FieldType) -> PointField {
+        PointField {
+            name: name.to_string(),
+            datatype,
+            count: 1,
+        }
+    }
+}
+
+/// Returns true for fields holding a color packed into a single 32-bit word.
+pub fn is_packed_color(name: &str) -> bool {
+    name == "rgb" || name == "rgba"
+}
+
+pub(crate) fn to_f64<T: ToPrimitive>(value: T) -> Result<f64> {
+    value
+        .to_f64()
+        .ok_or_else(|| anyhow!("Failed to convert field value to f64"))
+}
+
+pub(crate) fn from_f64<T: NumCast>(value: f64) -> Result<T> {
+    T::from(value).ok_or_else(|| anyhow!("Failed to convert {} to the field type", value))
+}
+
+pub(crate) fn packed_color_value<C: Color>(point: &C) -> f64
+where
+    <C as Color>::Item: ColorData,
+{
+    let rgb = point.rgb();
+    pack_rgb([rgb[0].to_u8(), rgb[1].to_u8(), rgb[2].to_u8()]) as f64
+}
+
+pub(crate) fn set_packed_color<C: Color>(point: &mut C, value: f64)
+where
+    <C as Color>::Item: ColorData,
+{
+    let [r, g, b] = unpack_rgb(value as u32);
+    *point.rgb_mut() = Vector3::new(
+        <C as Color>::Item::from_u8(r),
+        <C as Color>::Item::from_u8(g),
+        <C as Color>::Item::from_u8(b),
+    );
+}
+
+/// Inverse transpose of the linear part of a transform, or the part itself if singular.
+fn normal_matrix<N: RealField>(linear: &Matrix3<N>) -> Matrix3<N> {
+    linear
+        .try_inverse()
+        .map_or(*linear, |inverse| inverse.transpose())
+}
+
+pub(crate) fn transform_normal_vector<T: FloatData, U: FloatData>(
+    normal_matrix: &Matrix3<T>,
+    normal: &mut Vector3<U>,
+) {
+    let m = |i, j| U::from(normal_matrix[(i, j)]).unwrap_or_else(U::zero);
+    let n = *normal;
+    let mut transformed = [U::zero(); 3];
+    for (i, v) in transformed.iter_mut().enumerate() {
+        *v = m(i, 0) * n[0] + m(i, 1) * n[1] + m(i, 2) * n[2];
+    }
+    let norm = transformed
+        .iter()
+        .fold(U::zero(), |s, v| s + *v * *v)
+        .sqrt();
+    if norm > U::zero() {
+        *normal = Vector3::new(
+            transformed[0] / norm,
+            transformed[1] / norm,
+            transformed[2] / norm,
+        );
+    }
+}
+
 pub trait Point {
     type Item: FloatData;
     fn from_point(point: Vector3<Self::Item>) -> Self;
@@ -32,6 +248,57 @@ pub trait Point {
             &_ => Err(anyhow!(format!("Invalid field name {:?}", name))),
         }
     }
+    /// Fields carried by this point type, in serialization order.
+    fn fields() -> Vec<PointField>
+    where
+        Self: Sized,
+    {
+        let datatype = FieldType::float_of::<Self::Item>();
+        vec![
+            PointField::new("x", datatype),
+            PointField::new("y", datatype),
+            PointField::new("z", datatype),
+        ]
+    }
+    /// Value of the named field, as listed by `fields`.
+    ///
+    /// Packed colors (`rgb`, `rgba`) are returned as the integer value of the packed word.
+    fn field_value(&self, name: &str) -> Result<f64> {
+        to_f64(self.point_field(name)?)
+    }
+    /// Sets the named field from a value decoded from a file.
+    ///
+    /// Packed colors are passed as the integer value of the packed word. Values go
+    /// through `f64`, so 64-bit integers beyond 2^53 are rounded.
+    /// Returns an error if the point type has no such field.
+    fn set_field_value(&mut self, name: &str, value: f64) -> Result<()> {
+        *self.point_field_mut(name)? = from_f64(value)?;
+        Ok(())
+    }
+    /// Values of a field with `count` elements, such as a descriptor histogram.
+    ///
+    /// Scalar fields return a single value from `field_value`.
+    fn field_values(&self, name: &str) -> Result<Vec<f64>> {
+        Ok(vec![self.field_value(name)?])
+    }
+    /// Sets a field with `count` elements.
+    ///
+    /// Scalar fields accept a single value passed to `set_field_value`.
+    fn set_field_values(&mut self, name: &str, values: &[f64]) -> Result<()> {
+        match values {
+            [value] => self.set_field_value(name, *value),
+            _ => Err(anyhow!(format!(
+                "Field {:?} does not hold {} values",
+                name,
+                values.len()
+            ))),
+        }
+    }
+    /// Applies `normal_matrix`, the inverse transpose of the linear part of a transform,
+    /// to direction attributes such as normals, which are then renormalized.
+    ///
+    /// Point types without such attributes keep the default, which does nothing.
+    fn transform_normal(&mut self, _normal_matrix: &Matrix3<Self::Item>) {}
 }
 
 pub trait Color {
@@ -74,6 +341,19 @@ pub trait Normal {
     }
 }
 
+/// Point type carrying a return intensity.
+pub trait Intensity {
+    type Item: FloatData;
+    fn intensity(&self) -> Self::Item;
+    fn intensity_mut(&mut self) -> &mut Self::Item;
+}
+
+/// Point type carrying a semantic label.
+pub trait Label {
+    fn label(&self) -> u32;
+    fn label_mut(&mut self) -> &mut u32;
+}
+
 pub trait PointColor: Point + Color {
     fn from_point_color(
         point: Vector3<<Self as Point>::Item>,
@@ -107,7 +387,7 @@ where
 {
     type Item = T;
     fn from_point(point: Vector3<T>) -> PointXYZ<T> {
-        PointXYZ { point: point }
+        PointXYZ { point }
     }
     fn xyz(&self) -> &Vector3<T> {
         &self.point
@@ -151,7 +431,7 @@ where
     type Item = T;
     fn from_point(point: Vector3<T>) -> PointXYZNormal<T, U> {
         PointXYZNormal {
-            point: point,
+            point,
             normal: na::zero(),
         }
     }
@@ -161,6 +441,36 @@ where
     fn xyz_mut(&mut self) -> &mut Vector3<T> {
         &mut self.point
     }
+    fn fields() -> Vec<PointField> {
+        let datatype = FieldType::float_of::<T>();
+        let normal_type = FieldType::float_of::<U>();
+        vec![
+            PointField::new("x", datatype),
+            PointField::new("y", datatype),
+            PointField::new("z", datatype),
+            PointField::new("normal_x", normal_type),
+            PointField::new("normal_y", normal_type),
+            PointField::new("normal_z", normal_type),
+        ]
+    }
+    fn field_value(&self, name: &str) -> Result<f64> {
+        match name {
+            "normal_x" | "normal_y" | "normal_z" => to_f64(self.normal_field(name)?),
+            &_ => to_f64(self.point_field(name)?),
+        }
+    }
+    fn set_field_value(&mut self, name: &str, value: f64) -> Result<()> {
+        match name {
+            "normal_x" | "normal_y" | "normal_z" => {
+                *self.normal_field_mut(name)? = from_f64(value)?
+            }
+            &_ => *self.point_field_mut(name)? = from_f64(value)?,
+        }
+        Ok(())
+    }
+    fn transform_normal(&mut self, normal_matrix: &Matrix3<T>) {
+        transform_normal_vector(normal_matrix, &mut self.normal);
+    }
 }
 
 impl<T, U> Normal for PointXYZNormal<T, U>
@@ -183,10 +493,7 @@ where
     U: FloatData + ClosedAdd,
 {
     fn from_point_normal(point: Vector3<T>, normal: Vector3<U>) -> PointXYZNormal<T, U> {
-        PointXYZNormal {
-            point: point,
-            normal: normal,
-        }
+        PointXYZNormal { point, normal }
     }
 }
 
@@ -210,12 +517,12 @@ pub struct PointXYZRGB<T: FloatData, U: Scalar> {
 impl<T, U> Point for PointXYZRGB<T, U>
 where
     T: FloatData,
-    U: Scalar + Zero + ClosedAdd,
+    U: ColorData + Zero + ClosedAdd,
 {
     type Item = T;
     fn from_point(point: Vector3<T>) -> PointXYZRGB<T, U> {
         PointXYZRGB {
-            point: point,
+            point,
             color: na::zero(),
         }
     }
@@ -225,6 +532,28 @@ where
     fn xyz_mut(&mut self) -> &mut Vector3<T> {
         &mut self.point
     }
+    fn fields() -> Vec<PointField> {
+        let datatype = FieldType::float_of::<T>();
+        vec![
+            PointField::new("x", datatype),
+            PointField::new("y", datatype),
+            PointField::new("z", datatype),
+            PointField::new("rgb", FieldType::F32),
+        ]
+    }
+    fn field_value(&self, name: &str) -> Result<f64> {
+        match name {
+            "rgb" | "rgba" => Ok(packed_color_value(self)),
+            &_ => to_f64(self.point_field(name)?),
+        }
+    }
+    fn set_field_value(&mut self, name: &str, value: f64) -> Result<()> {
+        match name {
+            "rgb" | "rgba" => set_packed_color(self, value),
+            &_ => *self.point_field_mut(name)? = from_f64(value)?,
+        }
+        Ok(())
+    }
 }
 
 impl<T, U> Color for PointXYZRGB<T, U>
@@ -244,20 +573,17 @@ where
 impl<T, U> PointColor for PointXYZRGB<T, U>
 where
     T: FloatData,
-    U: Scalar + Zero + ClosedAdd,
+    U: ColorData + Zero + ClosedAdd,
 {
     fn from_point_color(point: Vector3<T>, color: Vector3<U>) -> PointXYZRGB<T, U> {
-        PointXYZRGB {
-            point: point,
-            color: color,
-        }
+        PointXYZRGB { point, color }
     }
 }
 
 impl<T, U> Add for PointXYZRGB<T, U>
 where
     T: FloatData + RealField,
-    U: FloatData + RealField,
+    U: ColorData + FloatData + RealField,
 {
     type Output = Self;
     fn add(self, other: Self) -> Self::Output {
@@ -275,13 +601,13 @@ pub struct PointXYZRGBNormal<T: FloatData, U: Scalar, V: FloatData> {
 impl<T, U, V> Point for PointXYZRGBNormal<T, U, V>
 where
     T: FloatData,
-    U: Scalar + Zero + ClosedAdd,
+    U: ColorData + Zero + ClosedAdd,
     V: FloatData + Zero + ClosedAdd,
 {
     type Item = T;
     fn from_point(point: Vector3<T>) -> PointXYZRGBNormal<T, U, V> {
         PointXYZRGBNormal {
-            point: point,
+            point,
             color: na::zero(),
             normal: na::zero(),
         }
@@ -292,6 +618,39 @@ where
     fn xyz_mut(&mut self) -> &mut Vector3<T> {
         &mut self.point
     }
+    fn fields() -> Vec<PointField> {
+        let datatype = FieldType::float_of::<T>();
+        let normal_type = FieldType::float_of::<V>();
+        vec![
+            PointField::new("x", datatype),
+            PointField::new("y", datatype),
+            PointField::new("z", datatype),
+            PointField::new("rgb", FieldType::F32),
+            PointField::new("normal_x", normal_type),
+            PointField::new("normal_y", normal_type),
+            PointField::new("normal_z", normal_type),
+        ]
+    }
+    fn field_value(&self, name: &str) -> Result<f64> {
+        match name {
+            "rgb" | "rgba" => Ok(packed_color_value(self)),
+            "normal_x" | "normal_y" | "normal_z" => to_f64(self.normal_field(name)?),
+            &_ => to_f64(self.point_field(name)?),
+        }
+    }
+    fn set_field_value(&mut self, name: &str, value: f64) -> Result<()> {
+        match name {
+            "rgb" | "rgba" => set_packed_color(self, value),
+            "normal_x" | "normal_y" | "normal_z" => {
+                *self.normal_field_mut(name)? = from_f64(value)?
+            }
+            &_ => *self.point_field_mut(name)? = from_f64(value)?,
+        }
+        Ok(())
+    }
+    fn transform_normal(&mut self, normal_matrix: &Matrix3<T>) {
+        transform_normal_vector(normal_matrix, &mut self.normal);
+    }
 }
 
 impl<T, U, V> Color for PointXYZRGBNormal<T, U, V>
@@ -327,7 +686,7 @@ where
 impl<T, U, V> Add for PointXYZRGBNormal<T, U, V>
 where
     T: FloatData + RealField,
-    U: FloatData + RealField,
+    U: ColorData + FloatData + RealField,
     V: FloatData + RealField,
 {
     type Output = Self;
@@ -340,15 +699,341 @@ where
     }
 }
 
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PointXYZI<T: FloatData> {
+    pub point: Vector3<T>,
+    pub intensity: T,
+}
+
+impl<T> Point for PointXYZI<T>
+where
+    T: FloatData,
+{
+    type Item = T;
+    fn from_point(point: Vector3<T>) -> PointXYZI<T> {
+        PointXYZI {
+            point,
+            intensity: T::zero(),
+        }
+    }
+    fn xyz(&self) -> &Vector3<T> {
+        &self.point
+    }
+    fn xyz_mut(&mut self) -> &mut Vector3<T> {
+        &mut self.point
+    }
+    fn fields() -> Vec<PointField> {
+        let datatype = FieldType::float_of::<T>();
+        vec![
+            PointField::new("x", datatype),
+            PointField::new("y", datatype),
+            PointField::new("z", datatype),
+            PointField::new("intensity", datatype),
+        ]
+    }
+    fn field_value(&self, name: &str) -> Result<f64> {
+        match name {
+            "intensity" => to_f64(self.intensity),
+            &_ => to_f64(self.point_field(name)?),
+        }
+    }
+    fn set_field_value(&mut self, name: &str, value: f64) -> Result<()> {
+        match name {
+            "intensity" => self.intensity = from_f64(value)?,
+            &_ => *self.point_field_mut(name)? = from_f64(value)?,
+        }
+        Ok(())
+    }
+}
+
+impl<T> Intensity for PointXYZI<T>
+where
+    T: FloatData,
+{
+    type Item = T;
+    fn intensity(&self) -> T {
+        self.intensity
+    }
+    fn intensity_mut(&mut self) -> &mut T {
+        &mut self.intensity
+    }
+}
+
+impl<T> Add for PointXYZI<T>
+where
+    T: FloatData + RealField,
+{
+    type Output = Self;
+    fn add(self, other: Self) -> Self::Output {
+        PointXYZI {
+            point: self.point + other.point,
+            intensity: self.intensity + other.intensity,
+        }
+    }
+}
+
+impl<T> Div<T> for PointXYZI<T>
+where
+    T: FloatData + RealField,
+{
+    type Output = Self;
+    fn div(self, other: T) -> Self::Output {
+        PointXYZI {
+            point: self.point / other,
+            intensity: self.intensity / other,
+        }
+    }
+}
+
+/// Point with a semantic label.
+///
+/// Labels cannot be averaged, so summing points keeps the label of the left operand
+/// and `voxel_grid_filter` keeps the label of one point of each voxel.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PointXYZL<T: FloatData> {
+    pub point: Vector3<T>,
+    pub label: u32,
+}
+
+impl<T> Point for PointXYZL<T>
+where
+    T: FloatData,
+{
+    type Item = T;
+    fn from_point(point: Vector3<T>) -> PointXYZL<T> {
+        PointXYZL { point, label: 0 }
+    }
+    fn xyz(&self) -> &Vector3<T> {
+        &self.point
+    }
+    fn xyz_mut(&mut self) -> &mut Vector3<T> {
+        &mut self.point
+    }
+    fn fields() -> Vec<PointField> {
+        let datatype = FieldType::float_of::<T>();
+        vec![
+            PointField::new("x", datatype),
+            PointField::new("y", datatype),
+            PointField::new("z", datatype),
+            PointField::new("label", FieldType::U32),
+        ]
+    }
+    fn field_value(&self, name: &str) -> Result<f64> {
+        match name {
+            "label" => Ok(self.label as f64),
+            &_ => to_f64(self.point_field(name)?),
+        }
+    }
+    fn set_field_value(&mut self, name: &str, value: f64) -> Result<()> {
+        match name {
+            "label" => self.label = from_f64(value)?,
+            &_ => *self.point_field_mut(name)? = from_f64(value)?,
+        }
+        Ok(())
+    }
+}
+
+impl<T> Label for PointXYZL<T>
+where
+    T: FloatData,
+{
+    fn label(&self) -> u32 {
+        self.label
+    }
+    fn label_mut(&mut self) -> &mut u32 {
+        &mut self.label
+    }
+}
+
+impl<T> Add for PointXYZL<T>
+where
+    T: FloatData + RealField,
+{
+    type Output = Self;
+    fn add(self, other: Self) -> Self::Output {
+        PointXYZL {
+            point: self.point + other.point,
+            label: self.label,
+        }
+    }
+}
+
+impl<T> Div<T> for PointXYZL<T>
+where
+    T: FloatData + RealField,
+{
+    type Output = Self;
+    fn div(self, other: T) -> Self::Output {
+        PointXYZL {
+            point: self.point / other,
+            label: self.label,
+        }
+    }
+}
+
+/// LiDAR point with intensity, laser ring and time offset, as published by
+/// Velodyne drivers.
+///
+/// Summing points keeps the ring of the left operand, so `voxel_grid_filter`
+/// averages intensity and time but keeps the ring of one point of each voxel.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PointXYZIRT<T: FloatData> {
+    pub point: Vector3<T>,
+    pub intensity: T,
+    pub ring: u16,
+    pub time: f32,
+}
+
+impl<T> Point for PointXYZIRT<T>
+where
+    T: FloatData,
+{
+    type Item = T;
+    fn from_point(point: Vector3<T>) -> PointXYZIRT<T> {
+        PointXYZIRT {
+            point,
+            intensity: T::zero(),
+            ring: 0,
+            time: 0.0,
+        }
+    }
+    fn xyz(&self) -> &Vector3<T> {
+        &self.point
+    }
+    fn xyz_mut(&mut self) -> &mut Vector3<T> {
+        &mut self.point
+    }
+    fn fields() -> Vec<PointField> {
+        let datatype = FieldType::float_of::<T>();
+        vec![
+            PointField::new("x", datatype),
+            PointField::new("y", datatype),
+            PointField::new("z", datatype),
+            PointField::new("intensity", datatype),
+            PointField::new("ring", FieldType::U16),
+            PointField::new("time", FieldType::F32),
+        ]
+    }
+    fn field_value(&self, name: &str) -> Result<f64> {
+        match name {
+            "intensity" => to_f64(self.intensity),
+            "ring" => Ok(self.ring as f64),
+            "time" => Ok(self.time as f64),
+            &_ => to_f64(self.point_field(name)?),
+        }
+    }
+    fn set_field_value(&mut self, name: &str, value: f64) -> Result<()> {
+        match name {
+            "intensity" => self.intensity = from_f64(value)?,
+            "ring" => self.ring = from_f64(value)?,
+            "time" => self.time = value as f32,
+            &_ => *self.point_field_mut(name)? = from_f64(value)?,
+        }
+        Ok(())
+    }
+}
+
+impl<T> Intensity for PointXYZIRT<T>
+where
+    T: FloatData,
+{
+    type Item = T;
+    fn intensity(&self) -> T {
+        self.intensity
+    }
+    fn intensity_mut(&mut self) -> &mut T {
+        &mut self.intensity
+    }
+}
+
+impl<T> Add for PointXYZIRT<T>
+where
+    T: FloatData + RealField,
+{
+    type Output = Self;
+    fn add(self, other: Self) -> Self::Output {
+        PointXYZIRT {
+            point: self.point + other.point,
+            intensity: self.intensity + other.intensity,
+            ring: self.ring,
+            time: self.time + other.time,
+        }
+    }
+}
+
+impl<T> Div<T> for PointXYZIRT<T>
+where
+    T: FloatData + RealField,
+{
+    type Output = Self;
+    fn div(self, other: T) -> Self::Output {
+        PointXYZIRT {
+            point: self.point / other,
+            intensity: self.intensity / other,
+            ring: self.ring,
+            time: self.time / other.to_f32().unwrap_or(1.0),
+        }
+    }
+}
+
+/// A set of points.
+///
+/// A cloud with `height > 1` is organized: its points are stored row-major in a
+/// `width` x `height` grid, and invalid points may be kept as NaN placeholders.
+/// Unorganized clouds have a `height` of 1, and a `width` of 1 or of their number of
+/// points when read from a file.
+#[derive(Clone)]
 pub struct PointCloud<T>
 where
     T: Point,
 {
     pub data: Vec<T>,
     pub width: u32,
+    pub height: u32,
     pub _marker: PhantomData<fn() -> T>,
 }
 
+impl<T> PointCloud<T>
+where
+    T: Point,
+{
+    /// Returns true if the points are laid out as a `width` x `height` grid.
+    pub fn is_organized(&self) -> bool {
+        self.height > 1
+    }
+    /// Point at `row` and `col` of an organized cloud.
+    ///
+    /// Panics if `row` or `col` is outside of the `width` x `height` grid.
+    pub fn at(&self, row: usize, col: usize) -> &T {
+        &self.data[self.grid_index(row, col)]
+    }
+    pub fn at_mut(&mut self, row: usize, col: usize) -> &mut T {
+        let index = self.grid_index(row, col);
+        &mut self.data[index]
+    }
+    fn grid_index(&self, row: usize, col: usize) -> usize {
+        assert!(
+            col < self.width as usize && row < self.height as usize,
+            "({}, {}) is outside of the {} x {} grid",
+            row,
+            col,
+            self.width,
+            self.height
+        );
+        row * self.width as usize + col
+    }
+}
+
+impl<T> Default for PointCloud<T>
+where
+    T: Point + Default,
+    <T as Point>::Item: FloatData + NumAssign,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> PointCloud<T>
 where
     T: Point + Default,
@@ -358,6 +1043,7 @@ where
         PointCloud {
             data: Vec::<T>::new(),
             width: 1,
+            height: 1,
             _marker: PhantomData,
         }
     }
@@ -366,12 +1052,16 @@ where
         PointCloud {
             data: points,
             width: 1,
+            height: 1,
             _marker: PhantomData,
         }
     }
     pub fn len(&self) -> usize {
         self.data.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
     pub fn item(&self, index: usize) -> &T {
         &self.data[index]
     }
@@ -380,43 +1070,123 @@ where
     }
     pub fn resize(&mut self, size: usize) {
         self.width = 1;
+        self.height = 1;
         self.data.resize_with(size, Default::default);
     }
     pub fn add_data(&mut self, element: T) {
         self.data.push(element);
     }
-    pub fn transform(&self, trans: &Matrix4<<T as Point>::Item>) -> PointCloud<T> {
-        let rot = trans.fixed_slice::<3, 3>(0, 0);
-        let t = trans.fixed_slice::<3, 1>(0, 3);
-        let mut transformed_pc = PointCloud::<T>::new();
-        transformed_pc.data = self
-            .data
-            .iter()
-            .map(|p| T::from_point(rot * p.xyz() + t))
-            .collect();
-        transformed_pc.width = self.width;
+    /// Applies a transform to every point, rotating normals and keeping other attributes.
+    ///
+    /// Normals are transformed by the inverse transpose of the linear part and
+    /// renormalized, so that they stay orthogonal to surfaces under scaling.
+    pub fn transform<M>(&self, trans: &M) -> PointCloud<T>
+    where
+        T: Clone,
+        M: AffineTransform<<T as Point>::Item>,
+        <T as Point>::Item: RealField,
+    {
+        let mut transformed_pc = self.clone();
+        transformed_pc.transform_mut(trans);
         transformed_pc
     }
-    pub fn rotation(&self, rot: &Matrix3<<T as Point>::Item>) -> PointCloud<T> {
-        let mut rotated_pc = PointCloud::<T>::new();
-        rotated_pc.data = self
-            .data
-            .iter()
-            .map(|p| T::from_point(rot * p.xyz()))
-            .collect();
-        rotated_pc.width = self.width;
+    /// In-place variant of `transform`.
+    pub fn transform_mut<M>(&mut self, trans: &M)
+    where
+        M: AffineTransform<<T as Point>::Item>,
+        <T as Point>::Item: RealField,
+    {
+        let trans = trans.to_matrix4();
+        let linear = trans.fixed_slice::<3, 3>(0, 0).into_owned();
+        let t = trans.fixed_slice::<3, 1>(0, 3).into_owned();
+        let normal_matrix = normal_matrix(&linear);
+        for p in self.data.iter_mut() {
+            let transformed = linear * p.xyz() + t;
+            *p.xyz_mut() = transformed;
+            p.transform_normal(&normal_matrix);
+        }
+    }
+    /// Rotates every point and normal, keeping other attributes.
+    ///
+    /// Normals are transformed as in `transform`, so a matrix that is not a pure
+    /// rotation keeps them orthogonal to surfaces.
+    pub fn rotation(&self, rot: &Matrix3<<T as Point>::Item>) -> PointCloud<T>
+    where
+        T: Clone,
+        <T as Point>::Item: RealField,
+    {
+        let mut rotated_pc = self.clone();
+        rotated_pc.rotation_mut(rot);
         rotated_pc
     }
-    pub fn translate(&self, t: &Vector3<<T as Point>::Item>) -> PointCloud<T> {
-        let mut translated_pc = PointCloud::<T>::new();
-        translated_pc.data = self
-            .data
-            .iter()
-            .map(|p| T::from_point(p.xyz() + t))
-            .collect();
-        translated_pc.width = self.width;
+    /// In-place variant of `rotation`.
+    pub fn rotation_mut(&mut self, rot: &Matrix3<<T as Point>::Item>)
+    where
+        <T as Point>::Item: RealField,
+    {
+        let normal_matrix = normal_matrix(rot);
+        for p in self.data.iter_mut() {
+            let rotated = rot * p.xyz();
+            *p.xyz_mut() = rotated;
+            p.transform_normal(&normal_matrix);
+        }
+    }
+    /// Translates every point, keeping other attributes.
+    pub fn translate(&self, t: &Vector3<<T as Point>::Item>) -> PointCloud<T>
+    where
+        T: Clone,
+    {
+        let mut translated_pc = self.clone();
+        translated_pc.translate_mut(t);
         translated_pc
     }
+    /// In-place variant of `translate`.
+    pub fn translate_mut(&mut self, t: &Vector3<<T as Point>::Item>) {
+        for p in self.data.iter_mut() {
+            *p.xyz_mut() += t;
+        }
+    }
+}
+
+/// Transform that can be applied to a point cloud, given as a homogeneous matrix.
+pub trait AffineTransform<N: RealField> {
+    fn to_matrix4(&self) -> Matrix4<N>;
+}
+
+impl<N: RealField> AffineTransform<N> for Matrix4<N> {
+    fn to_matrix4(&self) -> Matrix4<N> {
+        *self
+    }
+}
+
+impl<N: RealField> AffineTransform<N> for Isometry3<N> {
+    fn to_matrix4(&self) -> Matrix4<N> {
+        self.to_homogeneous()
+    }
+}
+
+impl<N: RealField> AffineTransform<N> for Similarity3<N> {
+    fn to_matrix4(&self) -> Matrix4<N> {
+        self.to_homogeneous()
+    }
+}
+
+impl<N: RealField> AffineTransform<N> for UnitQuaternion<N> {
+    fn to_matrix4(&self) -> Matrix4<N> {
+        self.to_homogeneous()
+    }
+}
+
+impl<N: RealField> AffineTransform<N> for Rotation3<N> {
+    fn to_matrix4(&self) -> Matrix4<N> {
+        self.to_homogeneous()
+    }
+}
+
+impl<N: RealField> AffineTransform<N> for Translation3<N> {
+    fn to_matrix4(&self) -> Matrix4<N> {
+        self.to_homogeneous()
+    }
 }
 
 impl<T> PointCloud<T>
@@ -431,12 +1201,13 @@ where
     ) -> PointCloud<T> {
         let points = data
             .into_iter()
-            .zip(colors.into_iter())
+            .zip(colors)
             .map(|(d, c)| T::from_point_color(d, c))
             .collect();
         PointCloud {
             data: points,
             width: 1,
+            height: 1,
             _marker: PhantomData,
         }
     }
@@ -446,3 +1217,165 @@ pub type PointCloudXYZ<T> = PointCloud<PointXYZ<T>>;
 pub type PointCloudXYZRGB<T> = PointCloud<PointXYZRGB<T, T>>;
 pub type PointCloudXYZNormal<T> = PointCloud<PointXYZNormal<T, T>>;
 pub type PointCloudXYZRGBNormal<T> = PointCloud<PointXYZRGBNormal<T, T, T>>;
+pub type PointCloudXYZI<T> = PointCloud<PointXYZI<T>>;
+pub type PointCloudXYZL<T> = PointCloud<PointXYZL<T>>;
+pub type PointCloudXYZIRT<T> = PointCloud<PointXYZIRT<T>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> PointCloud<PointXYZ<f32>> {
+        let mut pointcloud =
+            PointCloud::from_point_vec((0..6).map(|i| Vector3::new(i as f32, 0.0, 0.0)).collect());
+        pointcloud.width = 3;
+        pointcloud.height = 2;
+        pointcloud
+    }
+
+    #[test]
+    fn organized_access() {
+        let mut pointcloud = grid();
+        assert!(pointcloud.is_organized());
+        assert_eq!(pointcloud.at(1, 0).point[0], 3.0);
+        assert_eq!(pointcloud.at(1, 2).point[0], 5.0);
+        pointcloud.at_mut(0, 1).point[1] = 1.0;
+        assert_eq!(pointcloud.item(1).point[1], 1.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn column_out_of_grid() {
+        grid().at(0, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn row_out_of_grid() {
+        grid().at(2, 0);
+    }
+
+    #[test]
+    fn labeled_and_lidar_points() {
+        assert_eq!(
+            PointXYZIRT::<f32>::fields()
+                .iter()
+                .map(|f| (f.name.as_str(), f.datatype))
+                .collect::<Vec<_>>(),
+            vec![
+                ("x", FieldType::F32),
+                ("y", FieldType::F32),
+                ("z", FieldType::F32),
+                ("intensity", FieldType::F32),
+                ("ring", FieldType::U16),
+                ("time", FieldType::F32),
+            ]
+        );
+        assert_eq!(
+            PointXYZL::<f64>::fields()[3],
+            PointField::new("label", FieldType::U32)
+        );
+
+        let mut a = PointXYZIRT::<f64>::from_point(Vector3::new(1.0, 2.0, 3.0));
+        a.set_field_value("intensity", 10.0).unwrap();
+        a.set_field_value("ring", 5.0).unwrap();
+        a.set_field_value("time", 0.5).unwrap();
+        assert_eq!(a.field_value("ring").unwrap(), 5.0);
+        assert!(a.set_field_value("ring", -1.0).is_err());
+        let mut b = a;
+        b.intensity = 20.0;
+        b.ring = 9;
+        b.time = 1.5;
+        let mean = (a + b) / 2.0;
+        assert_eq!(mean.point, a.point);
+        assert_eq!(mean.intensity, 15.0);
+        assert_eq!(mean.ring, 5);
+        assert_eq!(mean.time, 1.0);
+
+        let a = PointXYZL::<f64> {
+            point: Vector3::new(2.0, 0.0, 0.0),
+            label: 3,
+        };
+        let b = PointXYZL::<f64> {
+            point: Vector3::new(4.0, 0.0, 0.0),
+            label: 8,
+        };
+        let mean = (a + b) / 2.0;
+        assert_eq!(mean.point, Vector3::new(3.0, 0.0, 0.0));
+        assert_eq!(mean.label, 3);
+        assert_eq!(mean.field_value("label").unwrap(), 3.0);
+    }
+
+    #[test]
+    fn lidar_points_pcd_round_trip() {
+        use crate::pcd::{read_pcd_from_bytes, write_pcd_to_writer, PCDEncoding};
+        let mut pointcloud = PointCloud::<PointXYZIRT<f32>>::new();
+        for i in 0..3 {
+            pointcloud.add_data(PointXYZIRT {
+                point: Vector3::new(i as f32, 0.5, -1.0),
+                intensity: 10.0 * i as f32,
+                ring: 15 - i,
+                time: 0.001 * i as f32,
+            });
+        }
+        let mut bytes = Vec::new();
+        write_pcd_to_writer(&pointcloud, &mut bytes, PCDEncoding::Binary).unwrap();
+        let header = String::from_utf8_lossy(&bytes[..200]).to_string();
+        assert!(
+            header.contains("FIELDS x y z intensity ring time"),
+            "{}",
+            header
+        );
+        assert!(header.contains("TYPE F F F F U F"), "{}", header);
+
+        let loaded: PointCloud<PointXYZIRT<f32>> = read_pcd_from_bytes(&bytes).unwrap();
+        for (a, b) in loaded.data.iter().zip(pointcloud.data.iter()) {
+            assert_eq!(a.point, b.point);
+            assert_eq!(a.intensity, b.intensity);
+            assert_eq!(a.ring, b.ring);
+            assert_eq!(a.time, b.time);
+        }
+        // Point types without the LiDAR fields drop them.
+        let labeled: PointCloud<PointXYZL<f32>> = read_pcd_from_bytes(&bytes).unwrap();
+        assert_eq!(labeled.item(2).point, pointcloud.item(2).point);
+        assert_eq!(labeled.item(2).label, 0);
+    }
+
+    #[test]
+    fn transform_normals() {
+        let normal = Vector3::new(-1.0, 0.0, 1.0).normalize();
+        let pointcloud = PointCloud::<PointXYZRGBNormal<f64, u8, f64>> {
+            data: vec![PointXYZRGBNormal {
+                point: Vector3::new(1.0, 2.0, 1.0),
+                color: Vector3::new(10, 20, 30),
+                normal,
+            }],
+            width: 1,
+            height: 1,
+            _marker: PhantomData,
+        };
+
+        // Scaling x by 2 turns the plane z = x into z = x / 2.
+        let scale = Matrix3::from_diagonal(&Vector3::new(2.0, 1.0, 1.0));
+        let expected = Vector3::new(-1.0, 0.0, 2.0).normalize();
+        let rotated = pointcloud.rotation(&scale);
+        let transformed = pointcloud.transform(&scale.to_homogeneous());
+        for p in [rotated.item(0), transformed.item(0)].iter() {
+            assert_eq!(p.point, Vector3::new(2.0, 2.0, 1.0));
+            assert!((p.normal - expected).norm() < 1e-12);
+            assert_eq!(p.color, Vector3::new(10, 20, 30));
+        }
+
+        // Isometries rotate normals and leave them untouched by the translation.
+        let isometry = Isometry3::new(
+            Vector3::new(0.0, 0.0, 5.0),
+            Vector3::new(0.0, 0.0, std::f64::consts::FRAC_PI_2),
+        );
+        let moved = pointcloud.transform(&isometry);
+        assert!((moved.item(0).point - Vector3::new(-2.0, 1.0, 6.0)).norm() < 1e-12);
+        assert!((moved.item(0).normal - Vector3::new(0.0, -1.0, 1.0).normalize()).norm() < 1e-12);
+        let translated = pointcloud.translate(&Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(translated.item(0).point, Vector3::new(2.0, 2.0, 1.0));
+        assert_eq!(translated.item(0).normal, normal);
+    }
+}
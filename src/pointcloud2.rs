@@ -0,0 +1,307 @@
+use super::dynamic_pointcloud::DynamicPointCloud;
+use super::pointcloud::{FieldType, FloatData, Point, PointCloud, PointField};
+use anyhow::{anyhow, Result};
+use core::convert::TryFrom;
+use num_traits::NumAssign;
+
+/// Field of a `PointCloud2`, laid out as `sensor_msgs/PointField`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PointCloud2Field {
+    pub name: String,
+    /// Byte offset of the field from the start of the point.
+    pub offset: u32,
+    /// ROS datatype constant, from `INT8 = 1` to `FLOAT64 = 8`.
+    pub datatype: u8,
+    pub count: u32,
+}
+
+/// Point cloud in the byte layout of ROS `sensor_msgs/PointCloud2`.
+///
+/// Unorganized clouds have a height of 1 and one point per column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PointCloud2 {
+    pub height: u32,
+    pub width: u32,
+    pub fields: Vec<PointCloud2Field>,
+    pub is_bigendian: bool,
+    pub point_step: u32,
+    pub row_step: u32,
+    pub data: Vec<u8>,
+    /// True if no point holds a non-finite coordinate.
+    pub is_dense: bool,
+}
+
+fn ros_datatype(datatype: FieldType) -> Result<u8> {
+    match datatype {
+        FieldType::I8 => Ok(1),
+        FieldType::U8 => Ok(2),
+        FieldType::I16 => Ok(3),
+        FieldType::U16 => Ok(4),
+        FieldType::I32 => Ok(5),
+        FieldType::U32 => Ok(6),
+        FieldType::F32 => Ok(7),
+        FieldType::F64 => Ok(8),
+        _ => Err(anyhow!(format!(
+            "{:?} fields are not supported by PointCloud2",
+            datatype
+        ))),
+    }
+}
+
+fn field_type(datatype: u8) -> Result<FieldType> {
+    match datatype {
+        1 => Ok(FieldType::I8),
+        2 => Ok(FieldType::U8),
+        3 => Ok(FieldType::I16),
+        4 => Ok(FieldType::U16),
+        5 => Ok(FieldType::I32),
+        6 => Ok(FieldType::U32),
+        7 => Ok(FieldType::F32),
+        8 => Ok(FieldType::F64),
+        _ => Err(anyhow!(format!(
+            "Invalid PointCloud2 datatype {}",
+            datatype
+        ))),
+    }
+}
+
+impl PointCloud2 {
+    /// Packs a `DynamicPointCloud` into little-endian PointCloud2 bytes without padding.
+    pub fn from_dynamic(pointcloud: &DynamicPointCloud) -> Result<PointCloud2> {
+        let mut fields = Vec::with_capacity(pointcloud.fields().len());
+        let mut offset = 0;
+        for f in pointcloud.fields().iter() {
+            fields.push(PointCloud2Field {
+                name: f.name.clone(),
+                offset: offset as u32,
+                datatype: ros_datatype(f.datatype)?,
+                count: f.count as u32,
+            });
+            offset += f.datatype.size() * f.count;
+        }
+        let n_points = pointcloud.len();
+        let mut data = Vec::with_capacity(n_points * offset);
+        for i in 0..n_points {
+            for (f, column) in pointcloud.fields().iter().zip(pointcloud.columns().iter()) {
+                for j in (i * f.count)..((i + 1) * f.count) {
+                    column.extend_le_bytes(j, &mut data);
+                }
+            }
+        }
+        let is_dense = (0..n_points).all(|i| {
+            ["x", "y", "z"].iter().all(|name| {
+                pointcloud
+                    .field_values(i, name)
+                    .map_or(true, |v| v.iter().all(|x| x.is_finite()))
+            })
+        });
+        let (width, height) = if pointcloud.is_organized() {
+            (pointcloud.width, pointcloud.height)
+        } else {
+            (n_points as u32, 1)
+        };
+        let point_step = u32::try_from(offset)
+            .map_err(|_| anyhow!(format!("Point size of {} bytes is too large", offset)))?;
+        let row_step = point_step.checked_mul(width).ok_or_else(|| {
+            anyhow!(format!(
+                "Rows of {} points of {} bytes are too large",
+                width, point_step
+            ))
+        })?;
+        Ok(PointCloud2 {
+            height,
+            width,
+            fields,
+            is_bigendian: false,
+            point_step,
+            row_step,
+            data,
+            is_dense,
+        })
+    }
+
+    /// Decodes every field into a `DynamicPointCloud`, keeping the organized layout.
+    pub fn to_dynamic(&self) -> Result<DynamicPointCloud> {
+        let point_step = self.point_step as usize;
+        let row_step = self.row_step as usize;
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let mut fields = Vec::with_capacity(self.fields.len());
+        for f in self.fields.iter() {
+            let datatype = field_type(f.datatype)?;
+            let end = f.offset as usize + datatype.size() * f.count as usize;
+            if end > point_step {
+                return Err(anyhow!(format!(
+                    "Field {:?} ends at byte {} past point_step {}",
+                    f.name, end, point_step
+                )));
+            }
+            fields.push(PointField {
+                name: f.name.clone(),
+                datatype,
+                count: f.count as usize,
+            });
+        }
+        if height > 0 && width > 0 {
+            if row_step < point_step * width {
+                return Err(anyhow!(format!(
+                    "row_step {} is smaller than {} points of {} bytes",
+                    row_step, width, point_step
+                )));
+            }
+            let expected = row_step * (height - 1) + point_step * width;
+            if self.data.len() < expected {
+                return Err(anyhow!(format!(
+                    "PointCloud2 data holds {} bytes, expected {}",
+                    self.data.len(),
+                    expected
+                )));
+            }
+        }
+
+        let mut pointcloud = DynamicPointCloud::new(fields);
+        let mut element = Vec::with_capacity(8);
+        for row in 0..height {
+            for col in 0..width {
+                let start = row * row_step + col * point_step;
+                let point = &self.data[start..(start + point_step)];
+                for (f, column) in self.fields.iter().zip(pointcloud.columns_mut().iter_mut()) {
+                    let size = column.datatype().size();
+                    for k in 0..f.count as usize {
+                        let offset = f.offset as usize + k * size;
+                        element.clear();
+                        element.extend_from_slice(&point[offset..(offset + size)]);
+                        if self.is_bigendian {
+                            element.reverse();
+                        }
+                        column.push_le_bytes(&element)?;
+                    }
+                }
+            }
+        }
+        if height > 1 {
+            pointcloud.width = self.width;
+            pointcloud.height = self.height;
+        }
+        Ok(pointcloud)
+    }
+
+    /// Packs a typed cloud, with the fields listed by `Point::fields`.
+    ///
+    /// Colors are stored as a packed `rgb` field, as published by PCL and most drivers.
+    pub fn from_pointcloud<T: Point>(pointcloud: &PointCloud<T>) -> Result<PointCloud2> {
+        PointCloud2::from_dynamic(&DynamicPointCloud::from_pointcloud(pointcloud)?)
+    }
+
+    /// Converts into a typed cloud, dropping the fields the point type does not carry.
+    ///
+    /// Every point is kept, so organized clouds keep their invalid (NaN) points.
+    pub fn to_pointcloud<T>(&self) -> Result<PointCloud<T>>
+    where
+        T: Point + Default,
+        <T as Point>::Item: FloatData + NumAssign,
+    {
+        self.to_dynamic()?.to_pointcloud()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::{PointXYZ, PointXYZRGB};
+    use nalgebra::Vector3;
+
+    #[test]
+    fn typed_round_trip() {
+        let cloud = PointCloud::<PointXYZRGB<f32, u8>>::from_point_color_vec(
+            vec![Vector3::new(1.0, 2.0, 3.0), Vector3::new(-4.0, 0.5, 6.0)],
+            vec![Vector3::new(255, 0, 10), Vector3::new(1, 2, 3)],
+        );
+        let msg = PointCloud2::from_pointcloud(&cloud).unwrap();
+        assert_eq!((msg.width, msg.height), (2, 1));
+        assert_eq!(msg.point_step, 16);
+        assert_eq!(msg.row_step, 32);
+        assert_eq!(msg.data.len(), 32);
+        assert!(msg.is_dense);
+
+        let read = msg.to_pointcloud::<PointXYZRGB<f32, u8>>().unwrap();
+        assert_eq!(read.len(), 2);
+        for (a, b) in read.data.iter().zip(cloud.data.iter()) {
+            assert_eq!(a.point, b.point);
+            assert_eq!(a.color, b.color);
+        }
+    }
+
+    #[test]
+    fn organized_round_trip() {
+        let mut cloud = PointCloud::<PointXYZ<f64>>::from_point_vec(vec![
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(f64::NAN, f64::NAN, f64::NAN),
+            Vector3::new(1.0, 0.0, 1.0),
+            Vector3::new(0.0, 1.0, 1.0),
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector3::new(2.0, 1.0, 1.0),
+        ]);
+        cloud.width = 3;
+        cloud.height = 2;
+        let msg = PointCloud2::from_pointcloud(&cloud).unwrap();
+        assert_eq!((msg.width, msg.height), (3, 2));
+        assert_eq!(msg.row_step, 3 * 24);
+        assert!(!msg.is_dense);
+
+        let read = msg.to_pointcloud::<PointXYZ<f64>>().unwrap();
+        assert_eq!((read.width, read.height), (3, 2));
+        assert!(read.at(0, 1).point.x.is_nan());
+        assert_eq!(read.at(1, 2).point, Vector3::new(2.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn big_endian_with_row_padding() {
+        let mut data = Vec::new();
+        for row in 0..2 {
+            for col in 0..2 {
+                for v in [col as f32, row as f32, 5.0].iter() {
+                    data.extend_from_slice(&v.to_be_bytes());
+                }
+                data.extend_from_slice(&(10 * row + col as u16).to_be_bytes());
+                data.extend_from_slice(&[0, 0]);
+            }
+            data.extend_from_slice(&[0; 4]);
+        }
+        let field = |name: &str, offset, datatype| PointCloud2Field {
+            name: name.to_string(),
+            offset,
+            datatype,
+            count: 1,
+        };
+        let msg = PointCloud2 {
+            height: 2,
+            width: 2,
+            fields: vec![
+                field("x", 0, 7),
+                field("y", 4, 7),
+                field("z", 8, 7),
+                field("ring", 12, 4),
+            ],
+            is_bigendian: true,
+            point_step: 16,
+            row_step: 36,
+            data,
+            is_dense: true,
+        };
+        let dynamic = msg.to_dynamic().unwrap();
+        assert_eq!(dynamic.field_values(3, "ring").unwrap(), vec![11.0]);
+        let read = msg.to_pointcloud::<PointXYZ<f32>>().unwrap();
+        assert_eq!((read.width, read.height), (2, 2));
+        assert_eq!(read.at(1, 0).point, Vector3::new(0.0, 1.0, 5.0));
+        assert_eq!(read.at(1, 1).point, Vector3::new(1.0, 1.0, 5.0));
+    }
+
+    #[test]
+    fn row_step_overflow() {
+        let mut cloud = DynamicPointCloud::new(vec![PointField::new("x", FieldType::F64)]);
+        cloud.width = u32::MAX / 2;
+        cloud.height = 2;
+        assert!(PointCloud2::from_dynamic(&cloud).is_err());
+    }
+}
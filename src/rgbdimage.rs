@@ -1,9 +1,113 @@
+use crate::camera::{CameraModel, PinholeCameraIntrinsic};
 use crate::pointcloud::*;
-use image::{ImageBuffer, Luma, RgbImage};
-use nalgebra::{Matrix3, Matrix4, Vector3};
-use num_traits::{FromPrimitive, NumAssign};
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, ImageBuffer, Luma, Rgb, RgbImage};
+use nalgebra::{Isometry3, Matrix4, Point3, Vector2, Vector3};
+use num_traits::{FromPrimitive, NumAssign, ToPrimitive};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 
-type FloatImage = ImageBuffer<Luma<f32>, Vec<f32>>;
+pub type FloatImage = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Reads a single channel depth image and converts it to metres.
+///
+/// 16-bit PNG / TIFF, 32 and 64-bit float TIFF, OpenEXR (the `Z` channel, or the only
+/// channel) and Radiance `.hdr` (first channel) files are accepted. Raw values are
+/// divided by `depth_scale`, e.g. 1000 for millimetres and 1 for float depth already in
+/// metres. Depths at or beyond `depth_trunc` are set to 0 (invalid); a non-positive
+/// `depth_trunc` keeps all of them.
+pub fn read_depth_image(filename: &str, depth_scale: f64, depth_trunc: f64) -> Result<FloatImage> {
+    if depth_scale <= 0.0 {
+        return Err(anyhow!(format!("Invalid depth scale {}", depth_scale)));
+    }
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let (width, height, raw) = match extension.as_deref() {
+        Some("tif") | Some("tiff") => read_tiff_depth(filename)?,
+        Some("exr") => read_exr_depth(filename)?,
+        Some("hdr") => {
+            let decoder = image::hdr::HdrDecoder::new(BufReader::new(File::open(filename)?))?;
+            let meta = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?;
+            let raw = pixels.iter().map(|p| p[0] as f64).collect();
+            (meta.width, meta.height, raw)
+        }
+        _ => match image::open(filename)? {
+            DynamicImage::ImageLuma16(image) => {
+                let raw = image.pixels().map(|p| p[0] as f64).collect();
+                (image.width(), image.height(), raw)
+            }
+            DynamicImage::ImageLuma8(image) => {
+                let raw = image.pixels().map(|p| p[0] as f64).collect();
+                (image.width(), image.height(), raw)
+            }
+            _ => {
+                return Err(anyhow!(format!(
+                    "Depth image {} must have a single channel",
+                    filename
+                )))
+            }
+        },
+    };
+    let depth = raw
+        .into_iter()
+        .map(|d: f64| {
+            let d = d / depth_scale;
+            if !d.is_finite() || d <= 0.0 || (depth_trunc > 0.0 && d >= depth_trunc) {
+                0.0
+            } else {
+                d as f32
+            }
+        })
+        .collect();
+    FloatImage::from_raw(width, height, depth)
+        .ok_or_else(|| anyhow!(format!("Truncated depth image {}", filename)))
+}
+
+fn read_tiff_depth(filename: &str) -> Result<(u32, u32, Vec<f64>)> {
+    use tiff::decoder::{Decoder, DecodingResult};
+    let mut decoder = Decoder::new(BufReader::new(File::open(filename)?))?;
+    match decoder.colortype()? {
+        tiff::ColorType::Gray(_) => {}
+        other => {
+            return Err(anyhow!(format!(
+                "Depth image {} must have a single channel, found {:?}",
+                filename, other
+            )))
+        }
+    }
+    let (width, height) = decoder.dimensions()?;
+    let raw = match decoder.read_image()? {
+        DecodingResult::U8(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U16(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U64(v) => v.into_iter().map(|d| d as f64).collect(),
+        DecodingResult::F32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::F64(v) => v,
+    };
+    Ok((width, height, raw))
+}
+
+fn read_exr_depth(filename: &str) -> Result<(u32, u32, Vec<f64>)> {
+    let image = exr::prelude::read_first_flat_layer_from_file(filename)?;
+    let layer = image.layer_data;
+    let channels = &layer.channel_data.list;
+    let channel = match channels.iter().find(|c| c.name.eq("Z")) {
+        Some(channel) => channel,
+        None if channels.len() == 1 => &channels[0],
+        None => {
+            return Err(anyhow!(format!(
+                "Depth image {} must have a Z channel or a single channel",
+                filename
+            )))
+        }
+    };
+    let raw = channel.sample_data.values_as_f32().map(f64::from).collect();
+    Ok((layer.size.width() as u32, layer.size.height() as u32, raw))
+}
 
 pub struct RGBDImage {
     pub color: RgbImage,
@@ -11,36 +115,658 @@ pub struct RGBDImage {
 }
 
 impl RGBDImage {
-    pub fn pointcloud<T>(
+    /// Loads a color image and a registered depth image of the same resolution.
+    ///
+    /// See `read_depth_image` for the accepted depth formats, `depth_scale` and
+    /// `depth_trunc`. With `convert_rgb_to_intensity`, the color image is replaced by its
+    /// intensity as in `convert_to_intensity`. The color image is read with the `image`
+    /// crate, so OpenEXR is only accepted for the depth image.
+    pub fn from_files(
+        color_path: &str,
+        depth_path: &str,
+        depth_scale: f64,
+        depth_trunc: f64,
+        convert_rgb_to_intensity: bool,
+    ) -> Result<RGBDImage> {
+        let color = image::open(color_path)?.to_rgb8();
+        let depth = read_depth_image(depth_path, depth_scale, depth_trunc)?;
+        if color.dimensions() != depth.dimensions() {
+            return Err(anyhow!(format!(
+                "Color image is {:?} but depth image is {:?}",
+                color.dimensions(),
+                depth.dimensions()
+            )));
+        }
+        let mut rgbd = RGBDImage { color, depth };
+        if convert_rgb_to_intensity {
+            rgbd.convert_to_intensity();
+        }
+        Ok(rgbd)
+    }
+
+    /// Color image converted to intensity in [0, 1].
+    pub fn intensity(&self) -> FloatImage {
+        FloatImage::from_fn(self.color.width(), self.color.height(), |x, y| {
+            let Rgb(c) = *self.color.get_pixel(x, y);
+            Luma([(0.299 * c[0] as f32 + 0.587 * c[1] as f32 + 0.114 * c[2] as f32) / 255.0])
+        })
+    }
+
+    /// Replaces the color image by its intensity, kept as gray RGB.
+    pub fn convert_to_intensity(&mut self) {
+        let intensity = self.intensity();
+        for (x, y, pixel) in self.color.enumerate_pixels_mut() {
+            let i = (intensity.get_pixel(x, y)[0] * 255.0).round() as u8;
+            *pixel = Rgb([i, i, i]);
+        }
+    }
+
+    /// Back-projects the valid depth pixels into a colored point cloud.
+    ///
+    /// Each point takes the color of the pixel at the same position. If the color image
+    /// has another resolution, pixel coordinates are scaled to it.
+    ///
+    /// `intrinsic` is either a 3x3 matrix or a `PinholeCameraIntrinsic`, whose lens
+    /// distortion is removed during back-projection.
+    pub fn pointcloud<T, I>(
+        &self,
+        intrinsic: I,
+        extrinsic: Matrix4<<T as Point>::Item>,
+        depth_cutoff: f64,
+    ) -> PointCloud<T>
+    where
+        T: PointColor + Default,
+        <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+        <T as Color>::Item: ColorData,
+        I: CameraModel,
+    {
+        self.pointcloud_with_layout(intrinsic, extrinsic, depth_cutoff, false)
+    }
+
+    /// Same as `pointcloud`, returning a cloud organized as the depth image, with NaN
+    /// positions for invalid pixels.
+    pub fn pointcloud_organized<T, I>(
+        &self,
+        intrinsic: I,
+        extrinsic: Matrix4<<T as Point>::Item>,
+        depth_cutoff: f64,
+    ) -> PointCloud<T>
+    where
+        T: PointColor + Default,
+        <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+        <T as Color>::Item: ColorData,
+        I: CameraModel,
+    {
+        self.pointcloud_with_layout(intrinsic, extrinsic, depth_cutoff, true)
+    }
+
+    fn pointcloud_with_layout<T, I>(
+        &self,
+        intrinsic: I,
+        extrinsic: Matrix4<<T as Point>::Item>,
+        depth_cutoff: f64,
+        organized: bool,
+    ) -> PointCloud<T>
+    where
+        T: PointColor + Default,
+        <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+        <T as Color>::Item: ColorData,
+        I: CameraModel,
+    {
+        self.back_project(intrinsic, extrinsic, depth_cutoff, organized, |x, y, _| {
+            self.color_of_depth_pixel(x, y)
+        })
+    }
+
+    /// Same as `pointcloud` for a color image taken by a second camera, such as a higher
+    /// resolution color camera next to the depth sensor.
+    ///
+    /// `depth_to_color` maps the depth camera frame to the color camera frame; points are
+    /// moved by it and then projected into the color image with `color_intrinsic`. Pass
+    /// the identity for a color image already registered to the depth image. Points
+    /// falling outside of the color image are black.
+    pub fn pointcloud_registered<T, I, J>(
+        &self,
+        depth_intrinsic: I,
+        color_intrinsic: J,
+        depth_to_color: &Isometry3<f64>,
+        extrinsic: Matrix4<<T as Point>::Item>,
+        depth_cutoff: f64,
+    ) -> PointCloud<T>
+    where
+        T: PointColor + Default,
+        <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+        <T as Color>::Item: ColorData,
+        I: CameraModel,
+        J: CameraModel,
+    {
+        self.pointcloud_registered_with_layout(
+            depth_intrinsic,
+            color_intrinsic,
+            depth_to_color,
+            extrinsic,
+            depth_cutoff,
+            false,
+        )
+    }
+
+    /// Same as `pointcloud_registered`, returning a cloud organized as the depth image.
+    pub fn pointcloud_registered_organized<T, I, J>(
+        &self,
+        depth_intrinsic: I,
+        color_intrinsic: J,
+        depth_to_color: &Isometry3<f64>,
+        extrinsic: Matrix4<<T as Point>::Item>,
+        depth_cutoff: f64,
+    ) -> PointCloud<T>
+    where
+        T: PointColor + Default,
+        <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+        <T as Color>::Item: ColorData,
+        I: CameraModel,
+        J: CameraModel,
+    {
+        self.pointcloud_registered_with_layout(
+            depth_intrinsic,
+            color_intrinsic,
+            depth_to_color,
+            extrinsic,
+            depth_cutoff,
+            true,
+        )
+    }
+
+    fn pointcloud_registered_with_layout<T, I, J>(
         &self,
-        intrinsic: Matrix3<<T as Point>::Item>,
+        depth_intrinsic: I,
+        color_intrinsic: J,
+        depth_to_color: &Isometry3<f64>,
+        extrinsic: Matrix4<<T as Point>::Item>,
+        depth_cutoff: f64,
+        organized: bool,
+    ) -> PointCloud<T>
+    where
+        T: PointColor + Default,
+        <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+        <T as Color>::Item: ColorData,
+        I: CameraModel,
+        J: CameraModel,
+    {
+        self.back_project(
+            depth_intrinsic,
+            extrinsic,
+            depth_cutoff,
+            organized,
+            |_, _, p| {
+                let pixel = color_intrinsic.project(&(depth_to_color * Point3::from(*p)).coords)?;
+                let (u, v) = (pixel[0].round(), pixel[1].round());
+                if u < 0.0 || v < 0.0 {
+                    return None;
+                }
+                self.color_pixel(u as u32, v as u32)
+            },
+        )
+    }
+
+    /// Renders a colored cloud into depth and color images of the size of `intrinsic`.
+    ///
+    /// This is the inverse of `pointcloud`: `extrinsic` maps the camera frame to the
+    /// cloud frame. See `render_depth` for `splat_radius` and `hole_fill_iterations`.
+    pub fn from_pointcloud<T>(
+        pointcloud: &PointCloud<T>,
+        intrinsic: &PinholeCameraIntrinsic,
+        extrinsic: Matrix4<<T as Point>::Item>,
+        splat_radius: u32,
+        hole_fill_iterations: u32,
+    ) -> Result<RGBDImage>
+    where
+        T: PointColor,
+        <T as Point>::Item: FloatData,
+        <T as Color>::Item: ColorData,
+    {
+        let (depth, color) = render(
+            pointcloud,
+            intrinsic,
+            extrinsic,
+            splat_radius,
+            hole_fill_iterations,
+            |p| {
+                let c = p.rgb();
+                Rgb([c[0].to_u8(), c[1].to_u8(), c[2].to_u8()])
+            },
+        )?;
+        Ok(RGBDImage { color, depth })
+    }
+
+    /// Color of the depth pixel `(x, y)`, scaling coordinates if the color image has
+    /// another resolution.
+    pub(crate) fn color_of_depth_pixel(&self, x: u32, y: u32) -> Option<&Rgb<u8>> {
+        let (dw, dh) = self.depth.dimensions();
+        let (cw, ch) = self.color.dimensions();
+        let u = (x as u64 * cw as u64 / dw as u64) as u32;
+        let v = (y as u64 * ch as u64 / dh as u64) as u32;
+        self.color_pixel(u, v)
+    }
+
+    fn color_pixel(&self, u: u32, v: u32) -> Option<&Rgb<u8>> {
+        if u < self.color.width() && v < self.color.height() {
+            Some(self.color.get_pixel(u, v))
+        } else {
+            None
+        }
+    }
+
+    /// Back-projects valid depth pixels, coloring each point with the pixel returned by
+    /// `color_at` for its depth pixel and position in the camera frame.
+    fn back_project<'a, T, I, F>(
+        &'a self,
+        intrinsic: I,
         extrinsic: Matrix4<<T as Point>::Item>,
         depth_cutoff: f64,
+        organized: bool,
+        color_at: F,
     ) -> PointCloud<T>
     where
         T: PointColor + Default,
         <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+        <T as Color>::Item: ColorData,
+        I: CameraModel,
+        F: Fn(u32, u32, &Vector3<f64>) -> Option<&'a Rgb<u8>>,
     {
-        let mut pointcloud = PointCloud::<T>::new();
-        let n_total = self.depth.width() * self.depth.height();
-        let rot = extrinsic.fixed_slice::<3, 3>(0, 0);
-        let t = extrinsic.fixed_slice::<3, 1>(0, 3);
-        pointcloud.resize(n_total as usize);
+        back_project_depth(
+            &self.depth,
+            intrinsic,
+            extrinsic,
+            depth_cutoff,
+            organized,
+            |x, y, p, world| {
+                let Rgb(c) = color_at(x, y, p).copied().unwrap_or(Rgb([0, 0, 0]));
+                T::from_point_color(
+                    world,
+                    Vector3::new(
+                        <T as Color>::Item::from_u8(c[0]),
+                        <T as Color>::Item::from_u8(c[1]),
+                        <T as Color>::Item::from_u8(c[2]),
+                    ),
+                )
+            },
+        )
+    }
+}
+
+/// Back-projects the valid pixels of a depth image in metres, for depth-only sensors.
+///
+/// A pixel is valid if its depth is positive and, when `depth_cutoff` is positive,
+/// below `depth_cutoff`. The returned cloud is unorganized.
+pub fn pointcloud_from_depth<T, I>(
+    depth: &FloatImage,
+    intrinsic: I,
+    extrinsic: Matrix4<<T as Point>::Item>,
+    depth_cutoff: f64,
+) -> PointCloud<T>
+where
+    T: Point + Default,
+    <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+    I: CameraModel,
+{
+    back_project_depth(
+        depth,
+        intrinsic,
+        extrinsic,
+        depth_cutoff,
+        false,
+        |_, _, _, p| T::from_point(p),
+    )
+}
+
+/// Same as `pointcloud_from_depth`, returning a cloud organized as the depth image.
+pub fn pointcloud_from_depth_organized<T, I>(
+    depth: &FloatImage,
+    intrinsic: I,
+    extrinsic: Matrix4<<T as Point>::Item>,
+    depth_cutoff: f64,
+) -> PointCloud<T>
+where
+    T: Point + Default,
+    <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+    I: CameraModel,
+{
+    back_project_depth(
+        depth,
+        intrinsic,
+        extrinsic,
+        depth_cutoff,
+        true,
+        |_, _, _, p| T::from_point(p),
+    )
+}
+
+/// Back-projects valid depth pixels and builds each point with `make_point` from its
+/// pixel, position in the camera frame and transformed position.
+///
+/// Organized clouds keep one point per pixel in row-major order, with a NaN position
+/// for invalid pixels.
+fn back_project_depth<T, I, F>(
+    depth: &FloatImage,
+    intrinsic: I,
+    extrinsic: Matrix4<<T as Point>::Item>,
+    depth_cutoff: f64,
+    organized: bool,
+    mut make_point: F,
+) -> PointCloud<T>
+where
+    T: Point + Default,
+    <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+    I: CameraModel,
+    F: FnMut(u32, u32, &Vector3<f64>, Vector3<<T as Point>::Item>) -> T,
+{
+    let mut pointcloud = PointCloud::<T>::new();
+    let n_total = depth.width() * depth.height();
+    let rot = extrinsic.fixed_slice::<3, 3>(0, 0);
+    let t = extrinsic.fixed_slice::<3, 1>(0, 3);
+    pointcloud.resize(n_total as usize);
+    let nan = <T as Point>::Item::from_f64(f64::NAN).unwrap();
+    let mut count = 0;
+    for (x, y, pixel) in depth.enumerate_pixels() {
+        let Luma(d) = *pixel;
+        if d[0] > 0.0 && (depth_cutoff <= 0.0 || depth_cutoff > d[0].into()) {
+            let p = intrinsic.back_project(&Vector2::new(x as f64, y as f64), d[0].into());
+            let world = rot * p.map(|v| <T as Point>::Item::from_f64(v).unwrap()) + t;
+            pointcloud.data[count] = make_point(x, y, &p, world);
+            count += 1;
+        } else if organized {
+            *pointcloud.data[count].xyz_mut() = Vector3::new(nan, nan, nan);
+            count += 1;
+        }
+    }
+    pointcloud.resize(count);
+    if organized {
+        pointcloud.width = depth.width();
+        pointcloud.height = depth.height();
+    }
+    pointcloud
+}
+
+/// Renders the depth in metres of a cloud seen by a camera, keeping the closest point
+/// of each pixel. Pixels without points have a depth of 0.
+///
+/// `extrinsic` maps the camera frame to the cloud frame, as in `pointcloud_from_depth`.
+/// Each point covers a square of `2 * splat_radius + 1` pixels. Holes are then filled
+/// with the mean of their valid neighbours during `hole_fill_iterations` passes; only
+/// pixels lying between two valid neighbours are filled, so that silhouettes do not grow.
+///
+/// Fails if `extrinsic` cannot be inverted.
+pub fn render_depth<T>(
+    pointcloud: &PointCloud<T>,
+    intrinsic: &PinholeCameraIntrinsic,
+    extrinsic: Matrix4<<T as Point>::Item>,
+    splat_radius: u32,
+    hole_fill_iterations: u32,
+) -> Result<FloatImage>
+where
+    T: Point,
+    <T as Point>::Item: FloatData,
+{
+    render(
+        pointcloud,
+        intrinsic,
+        extrinsic,
+        splat_radius,
+        hole_fill_iterations,
+        |_| Rgb([0, 0, 0]),
+    )
+    .map(|(depth, _)| depth)
+}
+
+fn render<T, F>(
+    pointcloud: &PointCloud<T>,
+    intrinsic: &PinholeCameraIntrinsic,
+    extrinsic: Matrix4<<T as Point>::Item>,
+    splat_radius: u32,
+    hole_fill_iterations: u32,
+    color_of: F,
+) -> Result<(FloatImage, RgbImage)>
+where
+    T: Point,
+    <T as Point>::Item: FloatData,
+    F: Fn(&T) -> Rgb<u8>,
+{
+    let (width, height) = (intrinsic.width, intrinsic.height);
+    let mut depth = FloatImage::new(width, height);
+    let mut color = RgbImage::new(width, height);
+    let extrinsic = extrinsic.map(|v| v.to_f64().unwrap_or(f64::NAN));
+    let world_to_camera = extrinsic
+        .try_inverse()
+        .ok_or_else(|| anyhow!("Camera extrinsic is not invertible"))?;
+    let rot = world_to_camera.fixed_slice::<3, 3>(0, 0);
+    let t = world_to_camera.fixed_slice::<3, 1>(0, 3);
+    let r = splat_radius as i64;
+    for point in pointcloud.data.iter() {
+        let p = point.xyz().map(|v| v.to_f64().unwrap_or(f64::NAN));
+        if !p.iter().all(|v| v.is_finite()) {
+            continue;
+        }
+        let p = rot * p + t;
+        let pixel = match intrinsic.project(&p) {
+            Some(pixel) => pixel,
+            None => continue,
+        };
+        // Far-out pixels would saturate the casts below; their splats miss the image.
+        let margin = r as f64 + 1.0;
+        if !(pixel[0] > -margin
+            && pixel[0] < width as f64 + margin
+            && pixel[1] > -margin
+            && pixel[1] < height as f64 + margin)
+        {
+            continue;
+        }
+        let (u, v) = (pixel[0].round() as i64, pixel[1].round() as i64);
+        let z = p[2] as f32;
+        for y in (v - r).max(0)..(v + r + 1).min(height as i64) {
+            for x in (u - r).max(0)..(u + r + 1).min(width as i64) {
+                let d = depth.get_pixel_mut(x as u32, y as u32);
+                if d[0] <= 0.0 || z < d[0] {
+                    *d = Luma([z]);
+                    color.put_pixel(x as u32, y as u32, color_of(point));
+                }
+            }
+        }
+    }
+    for _ in 0..hole_fill_iterations {
+        if !fill_holes(&mut depth, &mut color) {
+            break;
+        }
+    }
+    Ok((depth, color))
+}
+
+/// Fills each empty pixel lying between two valid neighbours on opposite sides with the
+/// mean depth and color of its valid neighbours. Returns false if no pixel was filled.
+fn fill_holes(depth: &mut FloatImage, color: &mut RgbImage) -> bool {
+    const OPPOSITE_PAIRS: [((i64, i64), (i64, i64)); 4] = [
+        ((-1, 0), (1, 0)),
+        ((0, -1), (0, 1)),
+        ((-1, -1), (1, 1)),
+        ((1, -1), (-1, 1)),
+    ];
+    let (width, height) = depth.dimensions();
+    let source_depth = depth.clone();
+    let source_color = color.clone();
+    let valid = |x: i64, y: i64| {
+        x >= 0
+            && y >= 0
+            && x < width as i64
+            && y < height as i64
+            && source_depth.get_pixel(x as u32, y as u32)[0] > 0.0
+    };
+    let mut filled = false;
+    for (x, y, pixel) in source_depth.enumerate_pixels() {
+        let (xi, yi) = (x as i64, y as i64);
+        if pixel[0] > 0.0
+            || !OPPOSITE_PAIRS
+                .iter()
+                .any(|((ax, ay), (bx, by))| valid(xi + ax, yi + ay) && valid(xi + bx, yi + by))
+        {
+            continue;
+        }
         let mut count = 0;
-        for (x, y, pixel) in self.depth.enumerate_pixels() {
-            let Luma(d) = *pixel;
-            if d[0] > 0.0 && (depth_cutoff <= 0.0 || depth_cutoff > d[0].into()) {
-                let pz = <T as Point>::Item::from_f32(d[0]).unwrap();
-                let px = (<T as Point>::Item::from_u32(x).unwrap() - intrinsic[(0, 2)]) * pz
-                    / intrinsic[(0, 0)];
-                let py = (<T as Point>::Item::from_u32(y).unwrap() - intrinsic[(1, 2)]) * pz
-                    / intrinsic[(1, 1)];
-                pointcloud.data[count] =
-                    T::from_point(rot * Vector3::<<T as Point>::Item>::new(px, py, pz) + t);
-                count += 1;
+        let mut sum_depth = 0.0;
+        let mut sum_color = [0u32; 3];
+        for ny in y.saturating_sub(1)..(y + 2).min(height) {
+            for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                let d = source_depth.get_pixel(nx, ny)[0];
+                if d > 0.0 {
+                    let Rgb(c) = *source_color.get_pixel(nx, ny);
+                    count += 1;
+                    sum_depth += d;
+                    for (s, c) in sum_color.iter_mut().zip(c.iter()) {
+                        *s += *c as u32;
+                    }
+                }
             }
         }
-        pointcloud.resize(count as usize);
-        pointcloud
+        depth.put_pixel(x, y, Luma([sum_depth / count as f32]));
+        color.put_pixel(
+            x,
+            y,
+            Rgb([
+                (sum_color[0] / count) as u8,
+                (sum_color[1] / count) as u8,
+                (sum_color[2] / count) as u8,
+            ]),
+        );
+        filled = true;
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 4x4 depth image at 2 m, seen through a camera whose rays are spread one metre
+    /// apart at that depth, with an 8x8 color image coding each pixel position.
+    fn rgbd() -> (RGBDImage, PinholeCameraIntrinsic) {
+        let mut depth = FloatImage::from_pixel(4, 4, Luma([2.0]));
+        depth.put_pixel(0, 3, Luma([0.0]));
+        let color = RgbImage::from_fn(8, 8, |u, v| Rgb([10 * u as u8, 10 * v as u8, 255]));
+        (
+            RGBDImage { color, depth },
+            PinholeCameraIntrinsic::new(4, 4, 2.0, 2.0, 1.5, 1.5),
+        )
+    }
+
+    #[test]
+    fn colors_of_scaled_image() {
+        let (rgbd, intrinsic) = rgbd();
+        let pointcloud: PointCloud<PointXYZRGB<f64, u8>> =
+            rgbd.pointcloud(intrinsic, Matrix4::identity(), 0.0);
+        assert_eq!(pointcloud.len(), 15);
+        // Depth pixel (x, y) = (2, 1) is the 7th valid pixel.
+        let p = pointcloud.item(6);
+        assert_eq!(p.point, Vector3::new(0.5, -0.5, 2.0));
+        assert_eq!(p.color, Vector3::new(40, 20, 255));
+    }
+
+    #[test]
+    fn colors_of_second_camera() {
+        let (rgbd, depth_intrinsic) = rgbd();
+        let color_intrinsic = PinholeCameraIntrinsic::new(8, 8, 4.0, 4.0, 3.5, 3.5);
+        // The color camera sees depth pixel (x, y) at (2x + 2, 2y + 1).
+        let depth_to_color = Isometry3::translation(0.75, 0.25, 0.0);
+        let extrinsic = Matrix4::new_translation(&Vector3::new(0.0, 0.0, 1.0));
+        let pointcloud: PointCloud<PointXYZRGB<f64, u8>> = rgbd.pointcloud_registered_organized(
+            depth_intrinsic,
+            color_intrinsic,
+            &depth_to_color,
+            extrinsic,
+            0.0,
+        );
+        assert_eq!((pointcloud.width, pointcloud.height), (4, 4));
+        assert!(pointcloud.at(3, 0).point[0].is_nan());
+        let p = pointcloud.at(2, 1);
+        assert_eq!(p.point, Vector3::new(-0.5, 0.5, 3.0));
+        assert_eq!(p.color, Vector3::new(40, 50, 255));
+        // Column 3 falls right of the color image.
+        assert_eq!(pointcloud.at(0, 3).color, Vector3::new(0, 0, 0));
+
+        let unorganized: PointCloud<PointXYZRGB<f64, u8>> = rgbd.pointcloud_registered(
+            depth_intrinsic,
+            color_intrinsic,
+            &Isometry3::identity(),
+            Matrix4::identity(),
+            0.0,
+        );
+        assert_eq!(unorganized.len(), 15);
+        // Without an offset, depth pixel (1, 1) lands on color pixel (2.5, 2.5) -> (3, 3).
+        assert_eq!(unorganized.item(5).color, Vector3::new(30, 30, 255));
+    }
+
+    #[test]
+    fn depth_only_pointcloud() {
+        let depth = FloatImage::from_fn(3, 2, |x, y| Luma([1.0 + x as f32 + 3.0 * y as f32]));
+        let k = nalgebra::Matrix3::new(1.0, 0.0, 1.0, 0.0, 1.0, 0.5, 0.0, 0.0, 1.0);
+        let extrinsic = Matrix4::new_translation(&Vector3::new(10.0, 0.0, 0.0));
+
+        // Depths of 5 m and more are cut off.
+        let pointcloud: PointCloud<PointXYZ<f32>> =
+            pointcloud_from_depth(&depth, k, extrinsic, 5.0);
+        assert_eq!(pointcloud.len(), 4);
+        assert_eq!(pointcloud.item(0).point, Vector3::new(9.0, -0.5, 1.0));
+        assert_eq!(pointcloud.item(3).point, Vector3::new(6.0, 2.0, 4.0));
+
+        let organized: PointCloud<PointXYZ<f32>> =
+            pointcloud_from_depth_organized(&depth, k, extrinsic, 5.0);
+        assert_eq!((organized.width, organized.height), (3, 2));
+        assert_eq!(organized.at(1, 0).point, pointcloud.item(3).point);
+        assert!(organized.at(1, 1).point[2].is_nan());
+        assert!(organized.at(1, 2).point[2].is_nan());
+
+        let all: PointCloud<PointXYZ<f32>> =
+            pointcloud_from_depth(&depth, k, Matrix4::identity(), 0.0);
+        assert_eq!(all.len(), 6);
+    }
+
+    #[test]
+    fn render_pointcloud() {
+        let intrinsic = PinholeCameraIntrinsic::new(5, 5, 2.0, 2.0, 2.0, 2.0);
+        let point = |x: f64, y: f64, z: f64, c: u8| PointXYZRGB {
+            point: Vector3::new(x, y, z),
+            color: Vector3::new(c, c, c),
+        };
+        let pointcloud = PointCloud::<PointXYZRGB<f64, u8>> {
+            data: vec![
+                // Pixels (1, 2) and (3, 2), 2 m in front of the camera.
+                point(-1.0, 0.0, 1.0, 100),
+                point(1.0, 0.0, 1.0, 200),
+                // Hidden behind the first point.
+                point(-2.0, 0.0, 3.0, 50),
+                point(f64::NAN, 0.0, 1.0, 0),
+                point(1e30, 0.0, 1.0, 0),
+                point(0.0, 0.0, -2.0, 0),
+            ],
+            width: 1,
+            height: 1,
+            _marker: std::marker::PhantomData,
+        };
+        // The camera stands 1 m behind the origin of the cloud.
+        let extrinsic = Matrix4::new_translation(&Vector3::new(0.0, 0.0, -1.0));
+
+        let depth = render_depth(&pointcloud, &intrinsic, extrinsic, 0, 0).unwrap();
+        let valid = depth.pixels().filter(|p| p[0] > 0.0).count();
+        assert_eq!(valid, 2);
+        assert_eq!(depth.get_pixel(1, 2)[0], 2.0);
+        assert_eq!(depth.get_pixel(3, 2)[0], 2.0);
+
+        let rgbd = RGBDImage::from_pointcloud(&pointcloud, &intrinsic, extrinsic, 0, 1).unwrap();
+        assert_eq!(rgbd.color.get_pixel(1, 2), &Rgb([100, 100, 100]));
+        // The pixel between both points is filled, the silhouette does not grow.
+        assert_eq!(rgbd.depth.get_pixel(2, 2)[0], 2.0);
+        assert_eq!(rgbd.color.get_pixel(2, 2), &Rgb([150, 150, 150]));
+        assert_eq!(rgbd.depth.get_pixel(0, 2)[0], 0.0);
+        assert_eq!(rgbd.depth.get_pixel(2, 1)[0], 0.0);
+
+        let splat = render_depth(&pointcloud, &intrinsic, extrinsic, 1, 0).unwrap();
+        assert_eq!(splat.pixels().filter(|p| p[0] > 0.0).count(), 15);
     }
 }
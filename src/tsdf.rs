@@ -0,0 +1,655 @@
+use crate::camera::CameraModel;
+use crate::mesh::TriangleMesh;
+use crate::pointcloud::*;
+use crate::rgbdimage::RGBDImage;
+use anyhow::{anyhow, Result};
+use nalgebra::{Matrix4, Vector2, Vector3};
+use num_traits::{FromPrimitive, NumAssign};
+use std::collections::{HashMap, HashSet};
+
+/// Truncated signed distance and color accumulated in a voxel.
+///
+/// `tsdf` is the signed distance to the surface divided by the truncation distance,
+/// positive in front of the surface. A voxel with a zero `weight` has never been observed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TSDFVoxel {
+    pub tsdf: f32,
+    pub weight: f32,
+    pub color: Vector3<f32>,
+}
+
+impl Default for TSDFVoxel {
+    fn default() -> Self {
+        TSDFVoxel {
+            tsdf: 1.0,
+            weight: 0.0,
+            color: Vector3::zeros(),
+        }
+    }
+}
+
+/// TSDF volume of `resolution`^3 voxels in a cube of side `length`, starting at `origin`.
+pub struct UniformTSDFVolume {
+    pub length: f64,
+    pub resolution: usize,
+    pub sdf_trunc: f64,
+    pub origin: Vector3<f64>,
+    voxels: Vec<TSDFVoxel>,
+}
+
+impl UniformTSDFVolume {
+    pub fn new(length: f64, resolution: usize, sdf_trunc: f64) -> UniformTSDFVolume {
+        UniformTSDFVolume {
+            length,
+            resolution,
+            sdf_trunc,
+            origin: Vector3::zeros(),
+            voxels: vec![TSDFVoxel::default(); resolution * resolution * resolution],
+        }
+    }
+
+    pub fn with_origin(self, origin: Vector3<f64>) -> UniformTSDFVolume {
+        UniformTSDFVolume { origin, ..self }
+    }
+
+    pub fn voxel_length(&self) -> f64 {
+        self.length / self.resolution as f64
+    }
+
+    /// Voxel at index `(x, y, z)`.
+    pub fn voxel(&self, x: usize, y: usize, z: usize) -> &TSDFVoxel {
+        &self.voxels[(z * self.resolution + y) * self.resolution + x]
+    }
+
+    pub fn reset(&mut self) {
+        self.voxels
+            .iter_mut()
+            .for_each(|v| *v = TSDFVoxel::default());
+    }
+
+    /// Fuses a depth frame, and its colors, seen by a camera whose pose in the volume
+    /// frame is `extrinsic`, as in `RGBDImage::pointcloud`.
+    ///
+    /// Fails if `extrinsic` cannot be inverted.
+    pub fn integrate<I: CameraModel>(
+        &mut self,
+        rgbd: &RGBDImage,
+        intrinsic: I,
+        extrinsic: Matrix4<f64>,
+    ) -> Result<()> {
+        let frame = Frame::new(rgbd, intrinsic, extrinsic, self.sdf_trunc)?;
+        let (resolution, voxel_length, origin) =
+            (self.resolution, self.voxel_length(), self.origin);
+        for (i, voxel) in self.voxels.iter_mut().enumerate() {
+            let index = [
+                (i % resolution) as i64,
+                (i / resolution % resolution) as i64,
+                (i / (resolution * resolution)) as i64,
+            ];
+            frame.update(voxel, &voxel_center(&origin, voxel_length, &index));
+        }
+        Ok(())
+    }
+
+    /// Points where the surface crosses the edges between observed voxels.
+    pub fn extract_point_cloud<T>(&self) -> PointCloud<T>
+    where
+        T: PointColor + Default,
+        <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+        <T as Color>::Item: ColorData,
+    {
+        extract_point_cloud(self)
+    }
+
+    /// Zero level set of the volume as a colored mesh, by marching cubes.
+    pub fn extract_triangle_mesh(&self) -> TriangleMesh {
+        extract_triangle_mesh(self)
+    }
+}
+
+/// TSDF volume allocating blocks of `volume_unit_resolution`^3 voxels around observed
+/// surfaces only, for scenes whose extent is not known in advance.
+pub struct ScalableTSDFVolume {
+    pub voxel_length: f64,
+    pub sdf_trunc: f64,
+    volume_unit_resolution: usize,
+    units: HashMap<[i64; 3], Vec<TSDFVoxel>>,
+}
+
+impl ScalableTSDFVolume {
+    /// Panics if `volume_unit_resolution` is 0.
+    pub fn new(
+        voxel_length: f64,
+        sdf_trunc: f64,
+        volume_unit_resolution: usize,
+    ) -> ScalableTSDFVolume {
+        assert!(
+            volume_unit_resolution > 0,
+            "volume_unit_resolution must be positive"
+        );
+        ScalableTSDFVolume {
+            voxel_length,
+            sdf_trunc,
+            volume_unit_resolution,
+            units: HashMap::new(),
+        }
+    }
+
+    pub fn volume_unit_resolution(&self) -> usize {
+        self.volume_unit_resolution
+    }
+
+    /// Number of allocated blocks.
+    pub fn volume_unit_count(&self) -> usize {
+        self.units.len()
+    }
+
+    /// Voxel at a global index, if its block is allocated. The voxel `[0, 0, 0]` spans
+    /// `[0, voxel_length)` on each axis.
+    pub fn voxel(&self, index: [i64; 3]) -> Option<&TSDFVoxel> {
+        let (unit, local) = self.split_index(&index);
+        self.units.get(&unit).map(|voxels| &voxels[local])
+    }
+
+    pub fn reset(&mut self) {
+        self.units.clear();
+    }
+
+    /// Fuses a depth frame, and its colors, seen by a camera whose pose in the volume
+    /// frame is `extrinsic`, as in `RGBDImage::pointcloud`.
+    ///
+    /// Blocks within `sdf_trunc` of the observed depths are allocated first. Fails if
+    /// `extrinsic` cannot be inverted.
+    pub fn integrate<I: CameraModel>(
+        &mut self,
+        rgbd: &RGBDImage,
+        intrinsic: I,
+        extrinsic: Matrix4<f64>,
+    ) -> Result<()> {
+        let frame = Frame::new(rgbd, intrinsic, extrinsic, self.sdf_trunc)?;
+        let resolution = self.volume_unit_resolution as i64;
+        let unit_length = self.voxel_length * resolution as f64;
+        let rot = extrinsic.fixed_slice::<3, 3>(0, 0);
+        let t = extrinsic.fixed_slice::<3, 1>(0, 3);
+        let n_samples = (4.0 * self.sdf_trunc / unit_length).ceil() as usize + 1;
+        let mut touched = HashSet::new();
+        for (x, y, pixel) in rgbd.depth.enumerate_pixels() {
+            let d = pixel[0] as f64;
+            if d <= 0.0 {
+                continue;
+            }
+            let ray = frame
+                .intrinsic
+                .back_project(&Vector2::new(x as f64, y as f64), 1.0);
+            let near = (d - self.sdf_trunc).max(0.0);
+            let far = d + self.sdf_trunc;
+            for i in 0..=n_samples {
+                let depth = near + (far - near) * i as f64 / n_samples as f64;
+                let p = rot * (ray * depth) + t;
+                touched.insert([
+                    (p[0] / unit_length).floor() as i64,
+                    (p[1] / unit_length).floor() as i64,
+                    (p[2] / unit_length).floor() as i64,
+                ]);
+            }
+        }
+        let n_voxels = self.volume_unit_resolution.pow(3);
+        let origin = Vector3::zeros();
+        for unit in touched {
+            let voxels = self
+                .units
+                .entry(unit)
+                .or_insert_with(|| vec![TSDFVoxel::default(); n_voxels]);
+            for (i, voxel) in voxels.iter_mut().enumerate() {
+                let i = i as i64;
+                let index = [
+                    unit[0] * resolution + i % resolution,
+                    unit[1] * resolution + i / resolution % resolution,
+                    unit[2] * resolution + i / (resolution * resolution),
+                ];
+                frame.update(voxel, &voxel_center(&origin, self.voxel_length, &index));
+            }
+        }
+        Ok(())
+    }
+
+    /// Points where the surface crosses the edges between observed voxels.
+    pub fn extract_point_cloud<T>(&self) -> PointCloud<T>
+    where
+        T: PointColor + Default,
+        <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+        <T as Color>::Item: ColorData,
+    {
+        extract_point_cloud(self)
+    }
+
+    /// Zero level set of the volume as a colored mesh, by marching cubes.
+    pub fn extract_triangle_mesh(&self) -> TriangleMesh {
+        extract_triangle_mesh(self)
+    }
+
+    fn split_index(&self, index: &[i64; 3]) -> ([i64; 3], usize) {
+        let r = self.volume_unit_resolution as i64;
+        let unit = [
+            index[0].div_euclid(r),
+            index[1].div_euclid(r),
+            index[2].div_euclid(r),
+        ];
+        let local =
+            (index[2].rem_euclid(r) * r + index[1].rem_euclid(r)) * r + index[0].rem_euclid(r);
+        (unit, local as usize)
+    }
+}
+
+fn voxel_center(origin: &Vector3<f64>, voxel_length: f64, index: &[i64; 3]) -> Vector3<f64> {
+    origin
+        + Vector3::new(
+            index[0] as f64 + 0.5,
+            index[1] as f64 + 0.5,
+            index[2] as f64 + 0.5,
+        ) * voxel_length
+}
+
+/// A depth frame being integrated.
+struct Frame<'a, I> {
+    rgbd: &'a RGBDImage,
+    intrinsic: I,
+    world_to_camera: Matrix4<f64>,
+    sdf_trunc: f64,
+}
+
+impl<'a, I: CameraModel> Frame<'a, I> {
+    fn new(
+        rgbd: &'a RGBDImage,
+        intrinsic: I,
+        extrinsic: Matrix4<f64>,
+        sdf_trunc: f64,
+    ) -> Result<Self> {
+        let world_to_camera = extrinsic
+            .try_inverse()
+            .ok_or_else(|| anyhow!("Camera extrinsic is not invertible"))?;
+        Ok(Frame {
+            rgbd,
+            intrinsic,
+            world_to_camera,
+            sdf_trunc,
+        })
+    }
+
+    /// Averages the truncated distance and color seen from this frame into `voxel`.
+    fn update(&self, voxel: &mut TSDFVoxel, center: &Vector3<f64>) {
+        let q = self.world_to_camera.fixed_slice::<3, 3>(0, 0) * center
+            + self.world_to_camera.fixed_slice::<3, 1>(0, 3);
+        let pixel = match self.intrinsic.project(&q) {
+            Some(pixel) => pixel,
+            None => return,
+        };
+        let (u, v) = (pixel[0].round(), pixel[1].round());
+        let (width, height) = self.rgbd.depth.dimensions();
+        if u < 0.0 || v < 0.0 || u >= width as f64 || v >= height as f64 {
+            return;
+        }
+        let (u, v) = (u as u32, v as u32);
+        let d = self.rgbd.depth.get_pixel(u, v)[0] as f64;
+        if d <= 0.0 {
+            return;
+        }
+        let sdf = d - q[2];
+        if sdf < -self.sdf_trunc {
+            return;
+        }
+        let tsdf = (sdf / self.sdf_trunc).min(1.0) as f32;
+        let weight = voxel.weight + 1.0;
+        voxel.tsdf = (voxel.tsdf * voxel.weight + tsdf) / weight;
+        if let Some(c) = self.rgbd.color_of_depth_pixel(u, v) {
+            let c = Vector3::new(c[0] as f32, c[1] as f32, c[2] as f32) / 255.0;
+            voxel.color = (voxel.color * voxel.weight + c) / weight;
+        }
+        voxel.weight = weight;
+    }
+}
+
+/// Read access to the voxels of a volume by global index.
+trait VoxelGrid {
+    fn voxel_at(&self, index: &[i64; 3]) -> Option<&TSDFVoxel>;
+    fn position(&self, index: &[i64; 3]) -> Vector3<f64>;
+    fn observed_indices(&self) -> Vec<[i64; 3]>;
+}
+
+impl VoxelGrid for UniformTSDFVolume {
+    fn voxel_at(&self, index: &[i64; 3]) -> Option<&TSDFVoxel> {
+        let r = self.resolution as i64;
+        if index.iter().all(|i| (0..r).contains(i)) {
+            Some(self.voxel(index[0] as usize, index[1] as usize, index[2] as usize))
+        } else {
+            None
+        }
+    }
+    fn position(&self, index: &[i64; 3]) -> Vector3<f64> {
+        voxel_center(&self.origin, self.voxel_length(), index)
+    }
+    fn observed_indices(&self) -> Vec<[i64; 3]> {
+        let r = self.resolution;
+        self.voxels
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.weight > 0.0)
+            .map(|(i, _)| [(i % r) as i64, (i / r % r) as i64, (i / (r * r)) as i64])
+            .collect()
+    }
+}
+
+impl VoxelGrid for ScalableTSDFVolume {
+    fn voxel_at(&self, index: &[i64; 3]) -> Option<&TSDFVoxel> {
+        self.voxel(*index)
+    }
+    fn position(&self, index: &[i64; 3]) -> Vector3<f64> {
+        voxel_center(&Vector3::zeros(), self.voxel_length, index)
+    }
+    fn observed_indices(&self) -> Vec<[i64; 3]> {
+        let r = self.volume_unit_resolution as i64;
+        let mut indices = Vec::new();
+        for (unit, voxels) in self.units.iter() {
+            for (i, voxel) in voxels.iter().enumerate() {
+                if voxel.weight > 0.0 {
+                    let i = i as i64;
+                    indices.push([
+                        unit[0] * r + i % r,
+                        unit[1] * r + i / r % r,
+                        unit[2] * r + i / (r * r),
+                    ]);
+                }
+            }
+        }
+        indices
+    }
+}
+
+fn observed<G: VoxelGrid>(grid: &G, index: &[i64; 3]) -> Option<TSDFVoxel> {
+    grid.voxel_at(index).filter(|v| v.weight > 0.0).copied()
+}
+
+fn offset(index: &[i64; 3], delta: &[i64; 3]) -> [i64; 3] {
+    [
+        index[0] + delta[0],
+        index[1] + delta[1],
+        index[2] + delta[2],
+    ]
+}
+
+const AXES: [[i64; 3]; 3] = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+/// Position and color where the surface crosses the edge from `index` along `axis`.
+fn edge_crossing<G: VoxelGrid>(
+    grid: &G,
+    index: &[i64; 3],
+    axis: usize,
+) -> Option<(Vector3<f64>, Vector3<f32>)> {
+    let other = offset(index, &AXES[axis]);
+    let a = observed(grid, index)?;
+    let b = observed(grid, &other)?;
+    if (a.tsdf < 0.0) == (b.tsdf < 0.0) {
+        return None;
+    }
+    let s = a.tsdf / (a.tsdf - b.tsdf);
+    let pa = grid.position(index);
+    let pb = grid.position(&other);
+    Some((pa + (pb - pa) * s as f64, a.color + (b.color - a.color) * s))
+}
+
+fn extract_point_cloud<G, T>(grid: &G) -> PointCloud<T>
+where
+    G: VoxelGrid,
+    T: PointColor + Default,
+    <T as Point>::Item: FloatData + FromPrimitive + NumAssign,
+    <T as Color>::Item: ColorData,
+{
+    let mut pointcloud = PointCloud::<T>::new();
+    for index in grid.observed_indices() {
+        for axis in 0..3 {
+            if let Some((p, c)) = edge_crossing(grid, &index, axis) {
+                let c = c.map(|v| <T as Color>::Item::from_u8((v * 255.0).round() as u8));
+                pointcloud.add_data(T::from_point_color(
+                    p.map(|v| <T as Point>::Item::from_f64(v).unwrap()),
+                    c,
+                ));
+            }
+        }
+    }
+    pointcloud
+}
+
+/// Corners of a cube, as offsets from its first voxel.
+const CUBE_CORNERS: [[i64; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [1, 1, 0],
+    [0, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [1, 1, 1],
+    [0, 1, 1],
+];
+
+/// Faces of a cube, as corners in cyclic order.
+const CUBE_FACES: [[usize; 4]; 6] = [
+    [0, 1, 2, 3],
+    [4, 5, 6, 7],
+    [0, 1, 5, 4],
+    [3, 2, 6, 7],
+    [0, 3, 7, 4],
+    [1, 2, 6, 5],
+];
+
+/// Marching cubes over every cube of 8 observed voxels.
+///
+/// Instead of the usual lookup tables, the surface polygons of a cube are found by
+/// linking the crossed edges of each face. Faces with 4 crossed edges are resolved by
+/// cutting off their inside corners, which only depends on the face itself, so that
+/// neighbouring cubes agree and the mesh has no cracks.
+fn extract_triangle_mesh<G: VoxelGrid>(grid: &G) -> TriangleMesh {
+    let mut mesh = TriangleMesh::new();
+    let mut vertex_ids = HashMap::<([i64; 3], usize), usize>::new();
+    for base in grid.observed_indices() {
+        let mut corners = [TSDFVoxel::default(); 8];
+        let mut complete = true;
+        for (corner, delta) in corners.iter_mut().zip(CUBE_CORNERS.iter()) {
+            match observed(grid, &offset(&base, delta)) {
+                Some(voxel) => *corner = voxel,
+                None => {
+                    complete = false;
+                    break;
+                }
+            }
+        }
+        if !complete {
+            continue;
+        }
+        let inside: Vec<bool> = corners.iter().map(|c| c.tsdf < 0.0).collect();
+        if inside.iter().all(|i| *i) || !inside.iter().any(|i| *i) {
+            continue;
+        }
+        // Crossed cube edges are keyed by their corner pair, lower corner first.
+        let edge = |a: usize, b: usize| if a < b { (a, b) } else { (b, a) };
+        let mut links = HashMap::<(usize, usize), Vec<(usize, usize)>>::new();
+        for face in CUBE_FACES.iter() {
+            let crossed: Vec<bool> = (0..4)
+                .map(|k| inside[face[k]] != inside[face[(k + 1) % 4]])
+                .collect();
+            let face_edge = |k: usize| edge(face[k % 4], face[(k + 1) % 4]);
+            let mut link = |a: (usize, usize), b: (usize, usize)| {
+                links.entry(a).or_default().push(b);
+                links.entry(b).or_default().push(a);
+            };
+            match crossed.iter().filter(|c| **c).count() {
+                2 => {
+                    let ks: Vec<usize> = (0..4).filter(|k| crossed[*k]).collect();
+                    link(face_edge(ks[0]), face_edge(ks[1]));
+                }
+                4 => {
+                    for k in 0..4 {
+                        if inside[face[k]] {
+                            link(face_edge(k + 3), face_edge(k));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut visited = HashSet::new();
+        let starts: Vec<(usize, usize)> = links.keys().copied().collect();
+        for start in starts {
+            if visited.contains(&start) {
+                continue;
+            }
+            let mut cycle = vec![start];
+            visited.insert(start);
+            let mut previous = start;
+            let mut current = links[&start][0];
+            while current != start {
+                visited.insert(current);
+                cycle.push(current);
+                let next = links[&current]
+                    .iter()
+                    .copied()
+                    .find(|e| *e != previous)
+                    .unwrap_or(start);
+                previous = current;
+                current = next;
+            }
+            if cycle.len() < 3 {
+                continue;
+            }
+            let mut ids = Vec::with_capacity(cycle.len());
+            let mut gradient = Vector3::zeros();
+            for &(a, b) in cycle.iter() {
+                let (pa, pb) = (
+                    offset(&base, &CUBE_CORNERS[a]),
+                    offset(&base, &CUBE_CORNERS[b]),
+                );
+                let axis = (0..3).find(|i| pa[*i] != pb[*i]).unwrap_or(0);
+                let direction = grid.position(&pb) - grid.position(&pa);
+                gradient += if inside[a] { direction } else { -direction };
+                let lower = if pa[axis] < pb[axis] { pa } else { pb };
+                let id = *vertex_ids.entry((lower, axis)).or_insert_with(|| {
+                    let (p, c) = edge_crossing(grid, &lower, axis).unwrap_or_default();
+                    mesh.vertices.push(p);
+                    mesh.vertex_colors.push(c);
+                    mesh.vertices.len() - 1
+                });
+                ids.push(id);
+            }
+            // Newell's normal of the polygon, turned towards the outside.
+            let mut normal = Vector3::zeros();
+            for i in 0..ids.len() {
+                let p = mesh.vertices[ids[i]];
+                let q = mesh.vertices[ids[(i + 1) % ids.len()]];
+                normal += p.cross(&q);
+            }
+            if normal.dot(&gradient) < 0.0 {
+                ids.reverse();
+            }
+            for i in 1..ids.len() - 1 {
+                mesh.triangles.push([ids[0], ids[i], ids[i + 1]]);
+            }
+        }
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::PinholeCameraIntrinsic;
+    use crate::rgbdimage::FloatImage;
+    use image::{Luma, Rgb, RgbImage};
+
+    const CENTER: [f64; 3] = [0.05, -0.05, 1.0];
+    const RADIUS: f64 = 0.3;
+
+    /// Depth and color of a sphere seen by a camera at the origin.
+    fn sphere() -> (RGBDImage, PinholeCameraIntrinsic) {
+        let intrinsic = PinholeCameraIntrinsic::new(64, 64, 60.0, 60.0, 31.5, 31.5);
+        let center = Vector3::from(CENTER);
+        let depth = FloatImage::from_fn(64, 64, |x, y| {
+            let ray = intrinsic
+                .back_project(&Vector2::new(x as f64, y as f64), 1.0)
+                .normalize();
+            // Closest intersection of the ray with the sphere.
+            let b = ray.dot(&center);
+            let disc = b * b - center.norm_squared() + RADIUS * RADIUS;
+            if disc < 0.0 {
+                return Luma([0.0]);
+            }
+            Luma([((b - disc.sqrt()) * ray[2]) as f32])
+        });
+        let color = RgbImage::from_pixel(64, 64, Rgb([255, 128, 0]));
+        (RGBDImage { color, depth }, intrinsic)
+    }
+
+    fn check_mesh(mesh: &TriangleMesh, tolerance: f64) {
+        assert!(
+            mesh.triangles.len() > 100,
+            "{} triangles",
+            mesh.triangles.len()
+        );
+        let center = Vector3::from(CENTER);
+        for v in mesh.vertices.iter() {
+            let error = ((v - center).norm() - RADIUS).abs();
+            assert!(
+                error < tolerance,
+                "vertex {} is {} off the sphere",
+                v,
+                error
+            );
+        }
+        let c = mesh.vertex_colors[0];
+        assert!((c - Vector3::new(1.0, 128.0 / 255.0, 0.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn uniform_volume_sphere() {
+        let (rgbd, intrinsic) = sphere();
+        let mut volume =
+            UniformTSDFVolume::new(0.8, 40, 0.06).with_origin(Vector3::new(-0.35, -0.45, 0.5));
+        volume
+            .integrate(&rgbd, intrinsic, Matrix4::identity())
+            .unwrap();
+        check_mesh(&volume.extract_triangle_mesh(), volume.voxel_length());
+
+        let pointcloud: PointCloud<PointXYZRGB<f64, u8>> = volume.extract_point_cloud();
+        assert!(!pointcloud.is_empty());
+        assert_eq!(pointcloud.item(0).color, Vector3::new(255, 128, 0));
+    }
+
+    #[test]
+    fn scalable_volume_sphere() {
+        let (rgbd, intrinsic) = sphere();
+        let mut volume = ScalableTSDFVolume::new(0.02, 0.06, 8);
+        // The camera moved 1 m along x: the sphere is seen at x + 1 in the volume.
+        let extrinsic = Matrix4::new_translation(&Vector3::new(1.0, 0.0, 0.0));
+        volume.integrate(&rgbd, intrinsic, extrinsic).unwrap();
+        assert!(volume.volume_unit_count() > 0);
+        let mut mesh = volume.extract_triangle_mesh();
+        for v in mesh.vertices.iter_mut() {
+            v[0] -= 1.0;
+        }
+        check_mesh(&mesh, 0.02);
+    }
+
+    #[test]
+    fn singular_extrinsic() {
+        let (rgbd, intrinsic) = sphere();
+        let mut volume = ScalableTSDFVolume::new(0.02, 0.06, 8);
+        assert!(volume
+            .integrate(&rgbd, intrinsic, Matrix4::zeros())
+            .is_err());
+        assert_eq!(volume.volume_unit_count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_volume_unit_resolution() {
+        ScalableTSDFVolume::new(0.02, 0.06, 0);
+    }
+}
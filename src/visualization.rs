@@ -16,7 +16,7 @@ use num_traits::ToPrimitive;
 
 use crate::pointcloud::*;
 
-const VERTEX_SHADER_SRC: &'static str = "#version 100
+const VERTEX_SHADER_SRC: &str = "#version 100
     attribute vec3 position;
     attribute vec3 color;
     varying   vec3 Color;
@@ -27,7 +27,7 @@ const VERTEX_SHADER_SRC: &'static str = "#version 100
         Color = color;
     }";
 
-const FRAGMENT_SHADER_SRC: &'static str = "#version 100
+const FRAGMENT_SHADER_SRC: &str = "#version 100
 #ifdef GL_FRAGMENT_PRECISION_HIGH
    precision highp float;
 #else
@@ -160,6 +160,12 @@ pub struct Visualizer {
     app: AppState,
 }
 
+impl Default for Visualizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Visualizer {
     pub fn new() -> Visualizer {
         let mut window = Window::new("Siskin");
@@ -168,10 +174,7 @@ impl Visualizer {
         let app = AppState {
             point_cloud_renderer: PointCloudRenderer::new(4.0),
         };
-        Visualizer {
-            window: window,
-            app: app,
-        }
+        Visualizer { window, app }
     }
     pub fn add_pointcloud<T>(&mut self, pc: &PointCloud<T>)
     where
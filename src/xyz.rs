@@ -0,0 +1,363 @@
+use super::pointcloud::{is_packed_color, pack_rgb, unpack_rgb, Point, PointCloud};
+use anyhow::{anyhow, Result};
+use csv::{ReaderBuilder, StringRecord};
+use num_traits::NumAssign;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+/// Layout of a plain text point file such as `.xyz`, `.txt` or `.csv`.
+///
+/// `columns` names the field stored in each column: point fields (`x`, `y`, `z`),
+/// normals (`nx`/`normal_x`, ...), 8-bit colors (`r`/`red`, `g`/`green`, `b`/`blue`)
+/// or any other field understood by the point type. Empty names skip the column.
+#[derive(Clone, Debug)]
+pub struct XYZFormat {
+    pub delimiter: u8,
+    pub columns: Vec<String>,
+    /// Number of leading lines to ignore, such as a CSV header.
+    pub skip_lines: usize,
+    /// Lines starting with this byte are ignored.
+    pub comment: Option<u8>,
+}
+
+impl Default for XYZFormat {
+    fn default() -> Self {
+        XYZFormat {
+            delimiter: b' ',
+            columns: vec!["x".to_string(), "y".to_string(), "z".to_string()],
+            skip_lines: 0,
+            comment: Some(b'#'),
+        }
+    }
+}
+
+impl XYZFormat {
+    /// Builds a format from space separated column names, e.g. `"x y z r g b"`.
+    pub fn with_columns(columns: &str) -> XYZFormat {
+        XYZFormat {
+            columns: columns.split_whitespace().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+fn point_field_name(name: &str) -> &str {
+    match name {
+        "nx" => "normal_x",
+        "ny" => "normal_y",
+        "nz" => "normal_z",
+        &_ => name,
+    }
+}
+
+fn color_channel(name: &str) -> Option<usize> {
+    match name {
+        "r" | "red" => Some(0),
+        "g" | "green" => Some(1),
+        "b" | "blue" => Some(2),
+        &_ => None,
+    }
+}
+
+fn get_data_from_record<T: Point + Default>(record: &[&str], columns: &[String]) -> Result<T> {
+    if record.len() < columns.len() {
+        return Err(anyhow!(format!(
+            "Expected {} columns, found {}",
+            columns.len(),
+            record.len()
+        )));
+    }
+    let mut data = T::default();
+    let mut rgb = None;
+    for (name, text) in columns.iter().zip(record.iter()) {
+        if name.is_empty() {
+            continue;
+        }
+        let value = text
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| anyhow!(format!("Invalid value {:?} for column {:?}", text, name)))?;
+        if let Some(channel) = color_channel(name) {
+            rgb.get_or_insert([0u8; 3])[channel] = value.clamp(0.0, 255.0) as u8;
+        } else {
+            // Fields the point type does not carry are ignored.
+            let _ = data.set_field_value(point_field_name(name), value);
+        }
+    }
+    if let Some(rgb) = rgb {
+        let _ = data.set_field_value("rgb", pack_rgb(rgb) as f64);
+    }
+    Ok(data)
+}
+
+/// Splits a record, merging runs of blanks when the delimiter is a space.
+fn split_record(record: &StringRecord, delimiter: u8) -> Vec<&str> {
+    if delimiter == b' ' || delimiter == b'\t' {
+        record.iter().filter(|s| !s.is_empty()).collect()
+    } else {
+        record.iter().collect()
+    }
+}
+
+fn read_records<R: BufRead>(
+    mut reader: R,
+    format: &XYZFormat,
+    mut f: impl FnMut(&[&str]) -> Result<()>,
+) -> Result<()> {
+    let mut buf = String::new();
+    for _ in 0..format.skip_lines {
+        buf.clear();
+        reader.read_line(&mut buf)?;
+    }
+    let mut reader = ReaderBuilder::new()
+        .delimiter(format.delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .comment(format.comment)
+        .from_reader(reader);
+    for result in reader.records() {
+        let record = result?;
+        let fields = split_record(&record, format.delimiter);
+        if !fields.is_empty() {
+            f(&fields)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a plain text point file laid out as described by `format`.
+pub fn read_xyz<T>(filename: &str, format: &XYZFormat) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_xyz_from_reader(BufReader::new(File::open(filename)?), format)
+}
+
+/// Same as `read_xyz`, reading from any buffered source.
+pub fn read_xyz_from_reader<T, R>(reader: R, format: &XYZFormat) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+    R: BufRead,
+{
+    let mut pointcloud = PointCloud::<T>::new();
+    read_records(reader, format, |record| {
+        pointcloud.add_data(get_data_from_record(record, &format.columns)?);
+        Ok(())
+    })?;
+    Ok(pointcloud)
+}
+
+fn format_record<T: Point>(point: &T, columns: &[String], delimiter: u8) -> Result<String> {
+    let mut tokens = Vec::with_capacity(columns.len());
+    for name in columns.iter() {
+        let token = if let Some(channel) = color_channel(name) {
+            let packed = point.field_value("rgb")?;
+            unpack_rgb(packed as u32)[channel].to_string()
+        } else if is_packed_color(name) {
+            (point.field_value(name)? as u32).to_string()
+        } else {
+            point.field_value(point_field_name(name))?.to_string()
+        };
+        tokens.push(token);
+    }
+    Ok(tokens.join(&(delimiter as char).to_string()))
+}
+
+/// Writes a point cloud as a plain text file with the columns of `format`.
+///
+/// Comment and skipped lines are not written.
+pub fn write_xyz<T>(pointcloud: &PointCloud<T>, filename: &str, format: &XYZFormat) -> Result<()>
+where
+    T: Point,
+{
+    let mut writer = BufWriter::new(File::create(filename)?);
+    write_xyz_to_writer(pointcloud, &mut writer, format)
+}
+
+/// Same as `write_xyz`, writing to any writer.
+pub fn write_xyz_to_writer<T, W>(
+    pointcloud: &PointCloud<T>,
+    writer: &mut W,
+    format: &XYZFormat,
+) -> Result<()>
+where
+    T: Point,
+    W: Write,
+{
+    for point in pointcloud.data.iter() {
+        writeln!(
+            writer,
+            "{}",
+            format_record(point, &format.columns, format.delimiter)?
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Column layout of a `.pts` file with `n` values per line.
+fn pts_columns(n: usize) -> Result<XYZFormat> {
+    match n {
+        3 => Ok(XYZFormat::with_columns("x y z")),
+        4 => Ok(XYZFormat::with_columns("x y z intensity")),
+        6 => Ok(XYZFormat::with_columns("x y z r g b")),
+        7 => Ok(XYZFormat::with_columns("x y z intensity r g b")),
+        _ => Err(anyhow!(format!("Unsupported PTS line with {} values", n))),
+    }
+}
+
+/// Reads a `.pts` file: a point count followed by `x y z [intensity] [r g b]` lines.
+pub fn read_pts<T>(filename: &str) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+{
+    read_pts_from_reader(BufReader::new(File::open(filename)?))
+}
+
+/// Same as `read_pts`, reading from any buffered source.
+pub fn read_pts_from_reader<T, R>(reader: R) -> Result<PointCloud<T>>
+where
+    T: Point + Default,
+    <T as Point>::Item: NumAssign,
+    R: BufRead,
+{
+    let mut pointcloud = PointCloud::<T>::new();
+    let mut n_points = None;
+    let mut format = None;
+    read_records(reader, &XYZFormat::default(), |record| {
+        if n_points.is_none() {
+            let n = record[0]
+                .parse::<usize>()
+                .map_err(|_| anyhow!(format!("Invalid PTS point count {:?}", record[0])))?;
+            n_points = Some(n);
+            return Ok(());
+        }
+        if format.is_none() {
+            format = Some(pts_columns(record.len())?);
+        }
+        if let Some(format) = &format {
+            pointcloud.add_data(get_data_from_record(record, &format.columns)?);
+        }
+        Ok(())
+    })?;
+    let n_points = n_points.ok_or_else(|| anyhow!("Missing PTS point count"))?;
+    if pointcloud.len() != n_points {
+        return Err(anyhow!(format!(
+            "PTS declares {} points but contains {}",
+            n_points,
+            pointcloud.len()
+        )));
+    }
+    Ok(pointcloud)
+}
+
+/// Writes a `.pts` file, including `r g b` columns if the point type has colors.
+pub fn write_pts<T>(pointcloud: &PointCloud<T>, filename: &str) -> Result<()>
+where
+    T: Point,
+{
+    let mut writer = BufWriter::new(File::create(filename)?);
+    write_pts_to_writer(pointcloud, &mut writer)
+}
+
+/// Same as `write_pts`, writing to any writer.
+pub fn write_pts_to_writer<T, W>(pointcloud: &PointCloud<T>, writer: &mut W) -> Result<()>
+where
+    T: Point,
+    W: Write,
+{
+    let has_color = T::fields().iter().any(|f| is_packed_color(&f.name));
+    let format = pts_columns(if has_color { 6 } else { 3 })?;
+    writeln!(writer, "{}", pointcloud.data.len())?;
+    write_xyz_to_writer(pointcloud, writer, &format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::{PointXYZ, PointXYZRGB, PointXYZRGBNormal};
+    use nalgebra::Vector3;
+
+    #[test]
+    fn csv_with_header_and_skipped_column() {
+        let text = "id,x,y,z,nx,ny,nz,red,green,blue\n\
+                    7,1.5,2,3,0,0,1,255,128,0\n\
+                    8,-1,0.25,4,1,0,0,0,0,255\n";
+        let format = XYZFormat {
+            delimiter: b',',
+            columns: ",x,y,z,nx,ny,nz,r,g,b"
+                .split(',')
+                .map(|s| s.to_string())
+                .collect(),
+            skip_lines: 1,
+            comment: None,
+        };
+        let pointcloud: PointCloud<PointXYZRGBNormal<f64, u8, f64>> =
+            read_xyz_from_reader(text.as_bytes(), &format).unwrap();
+        assert_eq!(pointcloud.len(), 2);
+        let p = pointcloud.item(0);
+        assert_eq!(p.point, Vector3::new(1.5, 2.0, 3.0));
+        assert_eq!(p.normal, Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(p.color, Vector3::new(255, 128, 0));
+        assert_eq!(pointcloud.item(1).color, Vector3::new(0, 0, 255));
+    }
+
+    #[test]
+    fn blanks_and_comments() {
+        let text = "# scan\n1  2   3\n\n  4 5 6 extra\n";
+        let pointcloud: PointCloud<PointXYZ<f32>> =
+            read_xyz_from_reader(text.as_bytes(), &XYZFormat::default()).unwrap();
+        assert_eq!(pointcloud.len(), 2);
+        assert_eq!(pointcloud.item(1).point, Vector3::new(4.0, 5.0, 6.0));
+        assert!(
+            read_xyz_from_reader::<PointXYZ<f32>, _>(&b"1 2\n"[..], &XYZFormat::default()).is_err()
+        );
+    }
+
+    fn colored_cloud() -> PointCloud<PointXYZRGB<f64, u8>> {
+        let mut pointcloud = PointCloud::new();
+        for i in 0..4u8 {
+            pointcloud.add_data(PointXYZRGB {
+                point: Vector3::new(i as f64 * 0.125, 1.0, -2.5),
+                color: Vector3::new(i, 2 * i, 255 - i),
+            });
+        }
+        pointcloud
+    }
+
+    #[test]
+    fn xyz_round_trip() {
+        let pointcloud = colored_cloud();
+        let format = XYZFormat::with_columns("x y z r g b");
+        let mut bytes = Vec::new();
+        write_xyz_to_writer(&pointcloud, &mut bytes, &format).unwrap();
+        let loaded: PointCloud<PointXYZRGB<f64, u8>> =
+            read_xyz_from_reader(&bytes[..], &format).unwrap();
+        for (a, b) in pointcloud.data.iter().zip(loaded.data.iter()) {
+            assert_eq!(a.point, b.point);
+            assert_eq!(a.color, b.color);
+        }
+    }
+
+    #[test]
+    fn pts() {
+        let text = "2\n1 2 3 -120 10 20 30\n4 5 6 87 40 50 60\n";
+        let pointcloud: PointCloud<PointXYZRGB<f64, u8>> =
+            read_pts_from_reader(text.as_bytes()).unwrap();
+        assert_eq!(pointcloud.len(), 2);
+        assert_eq!(pointcloud.item(1).point, Vector3::new(4.0, 5.0, 6.0));
+        assert_eq!(pointcloud.item(1).color, Vector3::new(40, 50, 60));
+        assert!(read_pts_from_reader::<PointXYZ<f64>, _>(&b"3\n1 2 3\n"[..]).is_err());
+
+        let pointcloud = colored_cloud();
+        let mut bytes = Vec::new();
+        write_pts_to_writer(&pointcloud, &mut bytes).unwrap();
+        assert!(bytes.starts_with(b"4\n"));
+        let loaded: PointCloud<PointXYZRGB<f64, u8>> = read_pts_from_reader(&bytes[..]).unwrap();
+        assert_eq!(loaded.len(), 4);
+        assert_eq!(loaded.item(3).color, Vector3::new(3, 6, 252));
+    }
+}